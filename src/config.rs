@@ -0,0 +1,86 @@
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Settings read from `~/.config/doto/config.toml`. Every key is optional.
+///
+/// ```toml
+/// [view]
+/// mode = "week"   # "days", "week" or "month"
+/// back = 7        # days before today shown in "days" mode
+/// forward = 14    # days after today shown in "days" mode
+/// later = false   # append later.md to the combined view
+///
+/// [editor]
+/// command = "code --wait"             # instead of $VISUAL / $EDITOR
+/// line_args = "--goto {file}:{line}"  # how to open a file at a line
+///
+/// [sync]
+/// concurrency = 8   # files transferred at the same time
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct Config {
+    pub view: ViewConfig,
+    pub editor: EditorConfig,
+    pub sync: SyncConfig,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ViewMode {
+    Days,
+    Week,
+    Month,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct ViewConfig {
+    pub mode: ViewMode,
+    pub back: i64,
+    pub forward: i64,
+    pub later: bool,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        ViewConfig {
+            mode: ViewMode::Days,
+            back: 4,
+            forward: 2,
+            later: true,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct EditorConfig {
+    pub command: Option<String>,
+    pub line_args: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub concurrency: usize,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        SyncConfig { concurrency: 4 }
+    }
+}
+
+pub fn get_config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config/doto/config.toml"))
+}
+
+pub fn get_config() -> Config {
+    let Some(config_path) = get_config_path().filter(|p| p.exists()) else {
+        return Config::default();
+    };
+    let content = std::fs::read_to_string(&config_path).expect("Failed to read config file");
+    toml::from_str(&content)
+        .unwrap_or_else(|err| panic!("Failed to parse config file {:?}: {}", config_path, err))
+}
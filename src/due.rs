@@ -0,0 +1,127 @@
+use crate::{
+    history::load_days,
+    task::{DayFile, Line, Task},
+    util::get_doto_path,
+};
+
+use chrono::NaiveDate;
+use std::path::PathBuf;
+
+/// Name of the section at the top of the combined view listing overdue tasks.
+pub const OVERDUE: &str = "overdue";
+
+const FROM_PREFIX: &str = " [from ";
+
+/// Open tasks with a `due:` date before today from the day files outside
+/// `start..=end`, and from later.md unless it's already in the view. Returns
+/// `(file name, line number, task)`, earliest due date first.
+pub fn overdue_tasks(start: NaiveDate, end: NaiveDate, later: bool) -> Vec<(String, usize, Task)> {
+    let today = chrono::Local::now().date_naive();
+    let mut files = load_days()
+        .into_iter()
+        .filter(|(date, _)| *date < start || *date > end)
+        .map(|(date, file)| (date.format("%Y-%m-%d").to_string(), file))
+        .collect::<Vec<_>>();
+    if !later {
+        let later_path = PathBuf::from(format!("{}/later.md", get_doto_path()));
+        if let Ok(later) = DayFile::load(&later_path) {
+            files.push(("later".to_string(), later));
+        }
+    }
+
+    let mut tasks = vec![];
+    for (name, file) in files {
+        for (i, line) in file.lines.iter().enumerate() {
+            match line {
+                Line::Task(task) if task.is_open() && task.due().is_some_and(|due| due < today) => {
+                    tasks.push((name.clone(), i + 1, task.clone()))
+                }
+                _ => {}
+            }
+        }
+    }
+    tasks.sort_by_key(|(_, _, task)| task.due());
+    tasks
+}
+
+/// Appends a ` [from 2023-05-20:3]` marker naming the file and line an overdue
+/// task lives at, so edits made in the combined view can be saved back to it.
+pub fn annotate_source(task: &Task, name: &str, line: usize) -> String {
+    let mut task = task.clone();
+    task.indent = String::new();
+    format!("{}{}{}:{}]", task, FROM_PREFIX, name, line)
+}
+
+/// Splits the marker added by [`annotate_source`] off a line.
+fn strip_source(line: &str) -> Option<(&str, &str, usize)> {
+    let rest = line.strip_suffix(']')?;
+    let (line, source) = rest.rsplit_once(FROM_PREFIX)?;
+    let (name, number) = source.rsplit_once(':')?;
+    Some((line, name, number.parse().ok()?))
+}
+
+/// Turns the overdue section of an edited combined view into updated
+/// `(file name, content)` pairs for the files its tasks came from. `sections`
+/// holds the rest of the view, so a file that is also in it gets the edit too.
+pub fn apply_overdue(
+    doto_path: &str,
+    overdue: &str,
+    sections: &[(String, String)],
+) -> Result<Vec<(String, String)>, Vec<String>> {
+    let mut files: Vec<(String, DayFile)> = vec![];
+    let mut errors = vec![];
+
+    for line in overdue.lines().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((edited, name, number)) =
+            strip_source(line).filter(|(l, ..)| Task::parse(l).is_some())
+        else {
+            errors.push(format!(
+                "\"{}\" can't be saved under \"# {}\", move it to a day",
+                line, OVERDUE
+            ));
+            continue;
+        };
+        let mut edited = Task::parse(edited).unwrap();
+
+        if !files.iter().any(|(n, _)| n == name) {
+            let content = match sections.iter().find(|(n, _)| n == name) {
+                Some((_, content)) => Some(content.clone()),
+                None => std::fs::read_to_string(format!("{}/{}.md", doto_path, name)).ok(),
+            };
+            let Some(content) = content else {
+                errors.push(format!("\"{}\": {}.md doesn't exist", line, name));
+                continue;
+            };
+            files.push((name.to_string(), DayFile::parse(&content)));
+        }
+        let file = &mut files.iter_mut().find(|(n, _)| n == name).unwrap().1;
+
+        let original = match number.checked_sub(1).and_then(|i| file.lines.get_mut(i)) {
+            Some(Line::Task(task)) if task.is_open() => Some(task),
+            _ => None,
+        };
+        match original {
+            Some(original) => {
+                edited.indent = original.indent.clone();
+                edited.section = original.section.clone();
+                *original = edited;
+            }
+            None => errors.push(format!(
+                "\"{}\": line {} of {}.md is no longer an open task, edit it there instead",
+                edited.text, number, name
+            )),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(files
+            .into_iter()
+            .map(|(name, file)| (name, file.to_string()))
+            .collect())
+    } else {
+        Err(errors)
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{
+    history::chain_key,
+    task::{DayFile, Heading, Line, Status, Task},
+    todo::{get_or_make_file, parse_day_string, resolve_filename},
+};
+
+use chrono::NaiveDate;
+use std::path::PathBuf;
+
+pub fn mark_done(selector: String, date: Option<String>) {
+    let (filename, path, mut day_file) = load_day(date);
+    let index = select_task(&day_file, &selector, |t| t.is_open());
+    let task = task_at(&mut day_file, index);
+    task.status = Status::Completed;
+    println!("Completed \"{}\" ({})", task.text, filename);
+    day_file.save(&path).expect("Could not write todo file");
+}
+
+/// Reopens a completed task, or takes back a deferred one by removing the
+/// copy that was added to the day it was deferred to.
+pub fn mark_undone(selector: String, date: Option<String>) {
+    let (filename, path, mut day_file) = load_day(date);
+    let index = select_task(&day_file, &selector, |t| !t.is_open());
+    let task = task_at(&mut day_file, index);
+
+    if let Some(moved_to) = task.moved_to.filter(|_| task.status == Status::Rescheduled) {
+        remove_deferred_copy(task, &filename, moved_to);
+    }
+    task.reopen();
+    println!("Reopened \"{}\" ({})", task.text, filename);
+    day_file.save(&path).expect("Could not write todo file");
+}
+
+/// Marks a task as `- [>] (date)` and adds it to the target day under the same
+/// section, the same way `doto --undone` does.
+pub fn defer_task(selector: String, date: Option<String>, to: Option<String>) {
+    let (filename, path, mut day_file) = load_day(date);
+    let index = select_task(&day_file, &selector, |t| t.is_open());
+
+    let target = match to {
+        Some(to) => parse_day_string(to).expect("Invalid date"),
+        None => chrono::Local::now().date_naive().succ_opt().unwrap(),
+    };
+    let target_filename = target.format("%Y-%m-%d").to_string();
+    if target_filename == filename {
+        eprintln!("The task is already on {}", filename);
+        std::process::exit(1);
+    }
+
+    let section = section_heading(&day_file, index);
+    let task = task_at(&mut day_file, index);
+    let mut copy = task.clone();
+    copy.indent = String::new();
+    copy.origin = NaiveDate::parse_from_str(&filename, "%Y-%m-%d").ok();
+    task.reschedule(target);
+    println!(
+        "Deferred \"{}\" from {} to {}",
+        task.text, filename, target_filename
+    );
+
+    let target_path = PathBuf::from(get_or_make_file(target_filename.clone()));
+    let mut target_file = DayFile::load(&target_path).expect("Could not read todo file");
+    target_file.insert_in_section(section.as_ref(), vec![copy]);
+
+    // write the copy first so a failure can't lose the task
+    target_file
+        .save(&target_path)
+        .expect("Could not write todo file");
+    day_file.save(&path).expect("Could not write todo file");
+}
+
+fn load_day(date: Option<String>) -> (String, PathBuf, DayFile) {
+    let filename = resolve_filename(date);
+    let path = PathBuf::from(get_or_make_file(filename.clone()));
+    let day_file = DayFile::load(&path).expect("Could not read todo file");
+    (filename, path, day_file)
+}
+
+fn task_at(day_file: &mut DayFile, index: usize) -> &mut Task {
+    match &mut day_file.lines[index] {
+        Line::Task(task) => task,
+        _ => unreachable!("selected line is not a task"),
+    }
+}
+
+/// The `##` heading above a line, if any.
+fn section_heading(day_file: &DayFile, index: usize) -> Option<Heading> {
+    day_file.lines[..index].iter().rev().find_map(|l| match l {
+        Line::Heading(h) if h.level > 1 => Some(Some(h.clone())),
+        Line::Heading(_) => Some(None),
+        _ => None,
+    })?
+}
+
+fn remove_deferred_copy(task: &Task, filename: &str, moved_to: NaiveDate) {
+    let origin = NaiveDate::parse_from_str(filename, "%Y-%m-%d").ok();
+    let target_path = PathBuf::from(get_or_make_file(moved_to.format("%Y-%m-%d").to_string()));
+    let mut target_file = DayFile::load(&target_path).expect("Could not read todo file");
+    let key = chain_key(&task.text);
+    let copy = target_file.lines.iter().position(|l| {
+        matches!(l, Line::Task(t) if t.is_open() && t.origin == origin && chain_key(&t.text) == key)
+    });
+    if let Some(copy) = copy {
+        target_file.lines.remove(copy);
+        remove_added_section(&mut target_file, copy);
+        target_file
+            .save(&target_path)
+            .expect("Could not write todo file");
+    }
+}
+
+/// Removes the `##` heading just before `index` if defer added it, which it
+/// does at the end of the file after a blank line, and nothing else is under
+/// it now.
+fn remove_added_section(day_file: &mut DayFile, index: usize) {
+    let is_blank = |l: &Line| l.to_string().trim().is_empty();
+    let added = index > 0
+        && matches!(&day_file.lines[index - 1], Line::Heading(h) if h.level > 1)
+        && day_file.lines[index..].iter().all(is_blank);
+    if !added {
+        return;
+    }
+    day_file.lines.truncate(index - 1);
+    if day_file.lines.last().is_some_and(is_blank) {
+        day_file.lines.pop();
+    }
+}
+
+/// Finds the line of the task a user picked, either by its number in the day
+/// file (as shown by `doto list`) or by matching its text. Text matches prefer
+/// the whole text, then a substring, then the letters in order.
+fn select_task(day_file: &DayFile, selector: &str, candidate: impl Fn(&Task) -> bool) -> usize {
+    let tasks = day_file
+        .lines
+        .iter()
+        .enumerate()
+        .filter_map(|(i, l)| match l {
+            Line::Task(task) => Some((i, task)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    if let Ok(number) = selector.parse::<usize>() {
+        let Some((index, task)) = number.checked_sub(1).and_then(|n| tasks.get(n)) else {
+            eprintln!("There is no task {} on this day", number);
+            std::process::exit(1);
+        };
+        if !candidate(task) {
+            eprintln!("Task {} can't be changed that way: {}", number, task);
+            std::process::exit(1);
+        }
+        return *index;
+    }
+
+    let query = selector.to_lowercase();
+    let mut matches = tasks
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, task))| candidate(task))
+        .filter_map(|(n, (i, task))| match_score(&task.text, &query).map(|s| (s, n, *i, *task)))
+        .collect::<Vec<_>>();
+    let Some(best) = matches.iter().map(|(score, ..)| *score).min() else {
+        eprintln!("No matching task found for \"{}\"", selector);
+        std::process::exit(1);
+    };
+    matches.retain(|(score, ..)| *score == best);
+
+    if matches.len() > 1 {
+        eprintln!("\"{}\" matches more than one task:", selector);
+        for (_, n, _, task) in &matches {
+            eprintln!("  {}: {}", n + 1, task.text);
+        }
+        eprintln!("Pick one by its number instead");
+        std::process::exit(1);
+    }
+    matches[0].2
+}
+
+fn match_score(text: &str, query: &str) -> Option<u8> {
+    let text = text.to_lowercase();
+    if text.trim() == query {
+        return Some(0);
+    }
+    if text.contains(query) {
+        return Some(1);
+    }
+    let mut chars = text.chars();
+    query.chars().all(|q| chars.any(|c| c == q)).then_some(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: &str = "# 2023-05-24
+- [ ] write report
+- [x] review report
+## home
+- [ ] water plants
+- [ ] report
+";
+
+    fn select(selector: &str, candidate: impl Fn(&Task) -> bool) -> String {
+        let day_file = DayFile::parse(DAY);
+        let index = select_task(&day_file, selector, candidate);
+        day_file.lines[index].to_string()
+    }
+
+    #[test]
+    fn selects_by_number() {
+        assert_eq!(select("1", |_| true), "- [ ] write report");
+        assert_eq!(select("2", |t| !t.is_open()), "- [x] review report");
+        assert_eq!(select("4", |_| true), "- [ ] report");
+    }
+
+    #[test]
+    fn prefers_whole_text_then_substring_then_letters() {
+        assert_eq!(select("report", |t| t.is_open()), "- [ ] report");
+        assert_eq!(select("Write", |t| t.is_open()), "- [ ] write report");
+        assert_eq!(select("wtrpl", |t| t.is_open()), "- [ ] water plants");
+        assert_eq!(select("report", |t| !t.is_open()), "- [x] review report");
+    }
+
+    #[test]
+    fn scores_matches() {
+        assert_eq!(match_score("Report ", "report"), Some(0));
+        assert_eq!(match_score("write report", "rite"), Some(1));
+        assert_eq!(match_score("write report", "wrrp"), Some(2));
+        assert_eq!(match_score("write report", "xyz"), None);
+        assert_eq!(match_score("report", "tr"), None);
+    }
+
+    #[test]
+    fn finds_the_section_of_a_task() {
+        let day_file = DayFile::parse(DAY);
+        assert_eq!(section_heading(&day_file, 1), None);
+        assert_eq!(
+            section_heading(&day_file, 5).map(|h| h.title),
+            Some("home".to_string())
+        );
+    }
+
+    /// Inserts a task into `content` the way defer does, then removes it the
+    /// way undo does.
+    fn defer_and_undo(content: &str, section: Option<&str>) -> String {
+        let mut day_file = DayFile::parse(content);
+        let heading = section.map(|title| Heading::new(2, title));
+        let index = day_file.insert_in_section(heading.as_ref(), vec![Task::new("deferred")]);
+        day_file.lines.remove(index);
+        remove_added_section(&mut day_file, index);
+        day_file.to_string()
+    }
+
+    #[test]
+    fn undo_removes_the_section_defer_added() {
+        let content = "# 2023-05-25\n- [ ] other\n";
+        assert_eq!(defer_and_undo(content, Some("work")), content);
+    }
+
+    #[test]
+    fn undo_keeps_sections_that_were_there() {
+        let content = "# 2023-05-25\n- [ ] other\n\n## work\n- [ ] existing\n";
+        assert_eq!(defer_and_undo(content, Some("work")), content);
+
+        let content = "# 2023-05-25\n- [ ] other\n\n## work\n- [ ] existing\n## home\n";
+        assert_eq!(defer_and_undo(content, Some("work")), content);
+        assert_eq!(defer_and_undo(content, None), content);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::config::get_config;
+
+use std::{path::Path, process::Command};
+
+/// Opens `file` in the user's editor, with the cursor on `line` if given.
+///
+/// The editor comes from `editor.command` in the config, then `$VISUAL`, then
+/// `$EDITOR`, and may include flags (e.g. `code --wait`).
+pub fn open_in_editor(file: &str, line: Option<usize>) {
+    let config = get_config().editor;
+    let editor = config
+        .command
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|e| !e.trim().is_empty())
+        .unwrap_or("vim".to_string());
+
+    let mut words = shell_words::split(&editor).unwrap_or_else(|err| {
+        eprintln!("Could not parse editor command \"{}\": {}", editor, err);
+        std::process::exit(1);
+    });
+    let program = words.remove(0);
+
+    let file_args = match line {
+        Some(line) => {
+            let template = config
+                .line_args
+                .unwrap_or(line_args_for(&program).to_string());
+            shell_words::split(&template)
+                .expect("Could not parse editor.line_args")
+                .into_iter()
+                .map(|arg| {
+                    arg.replace("{file}", file)
+                        .replace("{line}", &line.to_string())
+                })
+                .collect()
+        }
+        None => vec![file.to_string()],
+    };
+
+    Command::new(&program)
+        .args(words)
+        .args(file_args)
+        .status()
+        .unwrap_or_else(|err| panic!("Could not open {} with {}: {}", file, program, err));
+}
+
+/// Arguments that open a file at a line for well known editors.
+fn line_args_for(program: &str) -> &'static str {
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(program);
+    match name {
+        "vi" | "vim" | "nvim" | "gvim" | "mvim" | "emacs" | "emacsclient" | "nano" | "micro"
+        | "kak" => "+{line} {file}",
+        "hx" | "helix" | "subl" | "zed" => "{file}:{line}",
+        "code" | "code-insiders" | "codium" | "cursor" => "--goto {file}:{line}",
+        // unknown editors just get the file
+        _ => "{file}",
+    }
+}
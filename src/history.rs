@@ -0,0 +1,288 @@
+use crate::{
+    task::{date_from_path, parse_bracketed_date, DayFile, Status, Task},
+    todo::get_all_files,
+};
+
+use chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
+
+/// One task followed across every day file it was carried over to.
+pub struct Chain {
+    pub text: String,
+    /// each day the task appeared on, oldest first
+    pub days: Vec<(NaiveDate, Status)>,
+}
+
+impl Chain {
+    pub fn start(&self) -> NaiveDate {
+        self.days[0].0
+    }
+
+    pub fn completed(&self) -> Option<NaiveDate> {
+        self.days
+            .iter()
+            .find(|(_, status)| *status == Status::Completed)
+            .map(|(date, _)| *date)
+    }
+
+    /// Days between the first appearance and completion (or today if still open).
+    pub fn age(&self, today: NaiveDate) -> i64 {
+        let end = self.completed().unwrap_or(today);
+        (end - self.start()).num_days()
+    }
+}
+
+pub fn show_history(query: String) {
+    let today = chrono::Local::now().date_naive();
+    let query = chain_key(&query);
+    let chains = load_chains()
+        .into_iter()
+        .filter(|c| chain_key(&c.text).contains(&query))
+        .collect::<Vec<_>>();
+
+    if chains.is_empty() {
+        println!("No tasks found matching \"{}\"", query);
+        return;
+    }
+
+    for chain in chains {
+        println!("{}", chain.text);
+        for (date, status) in &chain.days {
+            let status = match status {
+                Status::Rescheduled => "rolled over",
+                status => status.name(),
+            };
+            println!("  {}  {}", date.format("%Y-%m-%d"), status);
+        }
+        match chain.completed() {
+            Some(_) => println!("  completed after {} days\n", chain.age(today)),
+            None => println!("  open for {} days\n", chain.age(today)),
+        }
+    }
+}
+
+/// Reads every day file in date order.
+pub fn load_days() -> Vec<(NaiveDate, DayFile)> {
+    let mut days = get_all_files()
+        .into_iter()
+        .filter_map(|f| date_from_path(&f).map(|date| (date, f)))
+        .collect::<Vec<_>>();
+    days.sort();
+    days.into_iter()
+        .map(|(date, f)| (date, DayFile::load(&f).expect("Could not read todo file")))
+        .collect()
+}
+
+pub fn load_chains() -> Vec<Chain> {
+    build_chains(&load_days())
+}
+
+/// Links each `- [>] (date)` task to its copy in the day file it was moved to,
+/// which points back with a trailing `(origin-date)`.
+pub fn build_chains(days: &[(NaiveDate, DayFile)]) -> Vec<Chain> {
+    let by_date: HashMap<NaiveDate, Vec<&Task>> = days
+        .iter()
+        .map(|(date, file)| (*date, file.tasks().collect()))
+        .collect();
+
+    let mut visited: HashSet<(NaiveDate, usize)> = HashSet::new();
+    let mut chains = vec![];
+    for (date, file) in days {
+        for (i, task) in file.tasks().enumerate() {
+            if visited.contains(&(*date, i)) {
+                continue;
+            }
+            visited.insert((*date, i));
+
+            let key = chain_key(&task.text);
+            let mut chain = Chain {
+                text: task.text.clone(),
+                days: vec![],
+            };
+            // the task was copied from a day we have no matching task for
+            if let Some(origin) = task.origin.filter(|o| o < date) {
+                chain.days.push((origin, Status::Rescheduled));
+            }
+            chain.days.push((*date, task.status));
+
+            let mut current = (*date, task);
+            while current.1.status == Status::Rescheduled {
+                let Some(next_date) = current.1.moved_to else {
+                    break;
+                };
+                let Some(candidates) = by_date.get(&next_date) else {
+                    break;
+                };
+                let next = candidates
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, t)| {
+                        !visited.contains(&(next_date, *j)) && chain_key(&t.text) == key
+                    })
+                    .min_by_key(|(_, t)| t.origin != Some(current.0));
+                let Some((j, next)) = next else {
+                    break;
+                };
+                visited.insert((next_date, j));
+                chain.days.push((next_date, next.status));
+                current = (next_date, next);
+            }
+
+            chains.push(chain);
+        }
+    }
+    chains
+}
+
+/// Start date of every carried-over task, keyed by the day it appears on and
+/// its text, so views can show how old a task is.
+pub fn task_starts() -> HashMap<(NaiveDate, String), NaiveDate> {
+    let mut starts = HashMap::new();
+    for chain in load_chains() {
+        let key = chain_key(&chain.text);
+        for (date, _) in &chain.days {
+            starts.insert((*date, key.clone()), chain.start());
+        }
+    }
+    starts
+}
+
+/// Normalises task text for matching copies across files. Older copies made by
+/// `--undone` stacked one `(date)` per move, so all of them are dropped.
+pub fn chain_key(text: &str) -> String {
+    let mut text = text.trim();
+    while let Some(split) = text.len().checked_sub(12) {
+        match text.get(split..).and_then(parse_bracketed_date) {
+            Some(_) => text = text[..split].trim_end(),
+            None => break,
+        }
+    }
+    text.to_lowercase()
+}
+
+const AGE_SUFFIX: &str = "d old]";
+
+/// Appends a ` [3d old]` marker to a task line for the combined view.
+pub fn annotate_age(line: &str, age: i64) -> String {
+    format!("{} [{}{}", line, age, AGE_SUFFIX)
+}
+
+/// Removes the marker added by [`annotate_age`] before the line is saved.
+pub fn strip_age(line: &str) -> &str {
+    let Some(rest) = line.strip_suffix(AGE_SUFFIX) else {
+        return line;
+    };
+    let digits = rest.trim_end_matches(|c: char| c.is_ascii_digit());
+    match digits.strip_suffix(" [") {
+        Some(stripped) if digits.len() < rest.len() => stripped,
+        _ => line,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn days(days: &[(&str, &str)]) -> Vec<(NaiveDate, DayFile)> {
+        days.iter()
+            .map(|(d, content)| (date(d), DayFile::parse(content)))
+            .collect()
+    }
+
+    fn history(chain: &Chain) -> Vec<(String, Status)> {
+        chain
+            .days
+            .iter()
+            .map(|(d, s)| (d.format("%Y-%m-%d").to_string(), *s))
+            .collect()
+    }
+
+    #[test]
+    fn chain_key_drops_stacked_dates() {
+        assert_eq!(chain_key("  Call Bank "), "call bank");
+        assert_eq!(
+            chain_key("call bank (2023-05-20) (2023-05-21)"),
+            "call bank"
+        );
+        assert_eq!(chain_key("(2023-05-20) dentist"), "(2023-05-20) dentist");
+        assert_eq!(
+            chain_key("not a date (2023-13-01)"),
+            "not a date (2023-13-01)"
+        );
+    }
+
+    #[test]
+    fn strips_only_age_markers() {
+        let line = annotate_age("- [ ] old task", 12);
+        assert_eq!(line, "- [ ] old task [12d old]");
+        assert_eq!(strip_age(&line), "- [ ] old task");
+        assert_eq!(strip_age("- [ ] no marker"), "- [ ] no marker");
+        assert_eq!(strip_age("- [ ] [d old]"), "- [ ] [d old]");
+        assert_eq!(strip_age("- [ ] x[3d old]"), "- [ ] x[3d old]");
+        assert_eq!(strip_age("- [ ] 10 [3d old]"), "- [ ] 10");
+    }
+
+    #[test]
+    fn follows_a_task_across_days() {
+        let chains = build_chains(&days(&[
+            ("2023-05-20", "- [>] (2023-05-21) report\n- [x] other\n"),
+            ("2023-05-21", "- [>] (2023-05-23) report (2023-05-20)\n"),
+            ("2023-05-23", "- [x] report (2023-05-21)\n"),
+        ]));
+        assert_eq!(chains.len(), 2);
+        assert_eq!(chains[0].text, "report");
+        assert_eq!(
+            history(&chains[0]),
+            [
+                ("2023-05-20".to_string(), Status::Rescheduled),
+                ("2023-05-21".to_string(), Status::Rescheduled),
+                ("2023-05-23".to_string(), Status::Completed),
+            ]
+        );
+        assert_eq!(chains[0].age(date("2023-06-01")), 3);
+        assert_eq!(chains[1].text, "other");
+    }
+
+    #[test]
+    fn prefers_the_copy_pointing_back_to_the_day() {
+        let chains = build_chains(&days(&[
+            ("2023-05-19", "- [>] (2023-05-21) call\n"),
+            ("2023-05-20", "- [>] (2023-05-21) call\n"),
+            (
+                "2023-05-21",
+                "- [ ] call (2023-05-20)\n- [x] call (2023-05-19)\n",
+            ),
+        ]));
+        assert_eq!(chains.len(), 2);
+        assert_eq!(
+            history(&chains[0])[1],
+            ("2023-05-21".to_string(), Status::Completed)
+        );
+        assert_eq!(
+            history(&chains[1])[1],
+            ("2023-05-21".to_string(), Status::Open)
+        );
+    }
+
+    #[test]
+    fn starts_from_an_origin_without_a_day_file() {
+        let chains = build_chains(&days(&[("2023-05-21", "- [ ] old (2023-05-10)\n")]));
+        assert_eq!(chains[0].start(), date("2023-05-10"));
+        assert_eq!(chains[0].age(date("2023-05-21")), 11);
+        assert_eq!(chains[0].completed(), None);
+    }
+
+    #[test]
+    fn stops_when_the_copy_is_missing() {
+        let chains = build_chains(&days(&[
+            ("2023-05-20", "- [>] (2023-05-21) lost\n"),
+            ("2023-05-21", "- [ ] something else\n"),
+        ]));
+        assert_eq!(chains.len(), 2);
+        assert_eq!(history(&chains[0]).len(), 1);
+    }
+}
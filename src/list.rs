@@ -0,0 +1,202 @@
+use crate::{
+    history::load_days,
+    tags::has_tags,
+    task::{DayFile, Status},
+    todo::parse_day_string,
+    util::get_doto_path,
+};
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::path::PathBuf;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Default)]
+pub struct ListFilter {
+    pub status: Option<Status>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub section: Option<String>,
+    pub text: Option<String>,
+    pub tags: Vec<String>,
+    pub due: bool,
+}
+
+/// One task as printed by `doto list`. `number` is its position in the day
+/// file, which is what `doto done`/`undo`/`defer` accept.
+#[derive(Serialize, Debug)]
+struct ListItem {
+    date: String,
+    number: usize,
+    status: Status,
+    section: Option<String>,
+    priority: Option<char>,
+    due: Option<String>,
+    text: String,
+}
+
+pub fn list_tasks(filter: ListFilter, format: OutputFormat) {
+    let from = filter
+        .from
+        .map(|d| parse_day_string(d).expect("Invalid --from date"));
+    let to = filter
+        .to
+        .map(|d| parse_day_string(d).expect("Invalid --to date"));
+    let section = filter.section.map(|s| s.to_lowercase());
+    let text = filter.text.map(|t| t.to_lowercase());
+    // due dates are about what's left to do
+    let status = match filter.status {
+        None if filter.due => Some(Status::Open),
+        status => status,
+    };
+
+    let mut files = load_days()
+        .into_iter()
+        .filter(|(date, _)| from.is_none_or(|from| *date >= from))
+        .filter(|(date, _)| to.is_none_or(|to| *date <= to))
+        .map(|(date, file)| (date.format("%Y-%m-%d").to_string(), file))
+        .collect::<Vec<_>>();
+    // later.md has no date, so it's only listed when no date range is given
+    if from.is_none() && to.is_none() {
+        let later_path = PathBuf::from(format!("{}/later.md", get_doto_path()));
+        if let Ok(later) = DayFile::load(&later_path) {
+            files.push(("later".to_string(), later));
+        }
+    }
+
+    let mut items = vec![];
+    for (date, file) in &files {
+        for (i, task) in file.tasks().enumerate() {
+            if status.is_some_and(|s| s != task.status) {
+                continue;
+            }
+            if !has_tags(task, &filter.tags) {
+                continue;
+            }
+            if filter.due && task.due().is_none() {
+                continue;
+            }
+            let task_section = task.section.as_ref().map(|s| s.to_lowercase());
+            if section.is_some() && task_section != section {
+                continue;
+            }
+            if let Some(text) = &text {
+                if !task.text.to_lowercase().contains(text) {
+                    continue;
+                }
+            }
+            items.push(ListItem {
+                date: date.clone(),
+                number: i + 1,
+                status: task.status,
+                section: task.section.clone(),
+                priority: task.priority(),
+                due: task.due().map(|d| d.format("%Y-%m-%d").to_string()),
+                text: task.text.clone(),
+            });
+        }
+    }
+    // tasks without a priority go last, the sort keeps each day's order
+    let priority = |item: &ListItem| (item.priority.is_none(), item.priority);
+    if filter.due {
+        items.sort_by(|a, b| (&a.due, priority(a)).cmp(&(&b.due, priority(b))));
+    } else {
+        items.sort_by(|a, b| (priority(a), &a.date).cmp(&(priority(b), &b.date)));
+    }
+
+    match format {
+        OutputFormat::Text if filter.due => print_due(&items),
+        OutputFormat::Text => print_text(&items),
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&items).expect("Failed to serialize tasks")
+        ),
+        OutputFormat::Csv => print_csv(&items),
+    }
+}
+
+fn print_text(items: &[ListItem]) {
+    // a day comes up once for each priority its tasks have
+    let mut group: Option<(Option<char>, &str)> = None;
+    let mut section: Option<&str> = None;
+    for item in items {
+        if group != Some((item.priority, item.date.as_str())) {
+            if group.is_some() {
+                println!();
+            }
+            println!("# {}", item.date);
+            group = Some((item.priority, &item.date));
+            section = None;
+        }
+        if section != item.section.as_deref() {
+            if let Some(title) = &item.section {
+                println!("## {}", title);
+            }
+            section = item.section.as_deref();
+        }
+        println!(
+            "{:>3}  - [{}] {}",
+            item.number,
+            item.status.mark(),
+            item.text
+        );
+    }
+}
+
+/// Tasks by due date, split into overdue and upcoming.
+fn print_due(items: &[ListItem]) {
+    let today = chrono::Local::now()
+        .date_naive()
+        .format("%Y-%m-%d")
+        .to_string();
+    let (overdue, upcoming): (Vec<_>, Vec<_>) = items
+        .iter()
+        .partition(|item| item.due.as_deref().is_some_and(|due| due < today.as_str()));
+    let groups = [("overdue", overdue), ("upcoming", upcoming)];
+    for (i, (title, items)) in groups.iter().filter(|(_, i)| !i.is_empty()).enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("# {}", title);
+        for item in items {
+            println!(
+                "{}  {:<10} {:>3}  - [{}] {}",
+                item.due.as_deref().unwrap_or(""),
+                item.date,
+                item.number,
+                item.status.mark(),
+                item.text
+            );
+        }
+    }
+}
+
+fn print_csv(items: &[ListItem]) {
+    println!("date,number,status,section,priority,due,text");
+    for item in items {
+        println!(
+            "{},{},{},{},{},{},{}",
+            item.date,
+            item.number,
+            item.status.name(),
+            csv_field(item.section.as_deref().unwrap_or("")),
+            item.priority.map(String::from).unwrap_or_default(),
+            item.due.as_deref().unwrap_or(""),
+            csv_field(&item.text)
+        );
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,46 @@
+use crate::util::get_doto_path;
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Write},
+};
+
+/// Holds an exclusive lock on `.doto.lock` in the doto directory until dropped.
+///
+/// The OS releases the lock when the process exits, so a crashed doto never
+/// leaves the directory locked.
+pub struct DotoLock {
+    _file: File,
+}
+
+pub fn lock_doto_dir() -> DotoLock {
+    let lock_path = format!("{}/.doto.lock", get_doto_path());
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&lock_path)
+        .expect("Failed to open lock file");
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            eprintln!(
+                "Another doto is already running ({}), try again once it has finished",
+                holder.trim()
+            );
+            std::process::exit(1);
+        }
+        Err(TryLockError::Error(err)) => panic!("Failed to lock {}: {}", lock_path, err),
+    }
+
+    // record who holds the lock so other processes can name it
+    let command = std::env::args().collect::<Vec<_>>().join(" ");
+    file.set_len(0).expect("Failed to write lock file");
+    write!(file, "pid {}: {}", std::process::id(), command).expect("Failed to write lock file");
+
+    DotoLock { _file: file }
+}
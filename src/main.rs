@@ -1,8 +1,20 @@
+mod config;
+mod due;
+mod edit;
+mod editor;
+mod history;
+mod list;
+mod lock;
+mod recurring;
+mod search;
+mod stats;
 mod sync;
+mod tags;
+mod task;
 mod todo;
 mod util;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -11,6 +23,14 @@ struct Cli {
     #[arg(short, long)]
     undone: bool,
 
+    /// With --undone, add the tasks to today's file highest priority first
+    #[arg(long, requires = "undone")]
+    by_priority: bool,
+
+    /// With --undone, print what would be moved without changing any files
+    #[arg(long, requires = "undone")]
+    dry_run: bool,
+
     /// Open file with date (e.g. today, tom, thu, 2021-01-01, 12-01, 07)
     ///
     /// Expects YYYY-MM-DD, MM-DD or DD
@@ -23,35 +43,275 @@ struct Cli {
     #[clap(short, long)]
     sync: bool,
 
+    /// Number of days before today to show in the combined view
+    #[arg(long)]
+    back: Option<i64>,
+
+    /// Number of days after today to show in the combined view
+    #[arg(long)]
+    forward: Option<i64>,
+
+    #[command(flatten)]
+    view: ViewArgs,
+
     #[clap(subcommand)]
     command: Option<Commands>,
 }
 
+/// Options for the commands that open a combined view.
+#[derive(Args, Debug)]
+struct ViewArgs {
+    /// Don't append later.md to the combined view
+    #[arg(long)]
+    no_later: bool,
+
+    /// Only show tasks with these `+project`/`@context` tags in the combined view
+    #[arg(long = "tag", id = "view_tags", value_name = "TAG")]
+    tags: Vec<String>,
+}
+
 #[derive(Subcommand, Debug)]
 enum Commands {
+    /// Open a combined view of this week, starting on Monday
+    Week {
+        #[command(flatten)]
+        view: ViewArgs,
+    },
+
+    /// Open a combined view of this month
+    Month {
+        #[command(flatten)]
+        view: ViewArgs,
+    },
+
+    /// Add a task without opening the editor
+    Add {
+        /// Text of the task
+        text: String,
+
+        /// Day to add the task to (e.g. today, tom, thu, 2021-01-01 or later), defaults to today
+        #[arg(short, long)]
+        date: Option<String>,
+
+        /// `##` section to add the task under, created if it doesn't exist
+        #[arg(short, long)]
+        section: Option<String>,
+
+        /// Open the editor on the new task afterwards
+        #[arg(short, long)]
+        edit: bool,
+    },
+
+    /// Mark a task as done
+    Done {
+        /// Number of the task in the day (as shown by `doto list`) or part of its text
+        task: String,
+
+        /// Day the task is on, defaults to today
+        #[arg(short, long)]
+        date: Option<String>,
+    },
+
+    /// Reopen a completed task, or take back a deferred one
+    Undo {
+        /// Number of the task in the day (as shown by `doto list`) or part of its text
+        task: String,
+
+        /// Day the task is on, defaults to today
+        #[arg(short, long)]
+        date: Option<String>,
+    },
+
+    /// Move a task to another day, marking it as `- [>] (date)`
+    Defer {
+        /// Number of the task in the day (as shown by `doto list`) or part of its text
+        task: String,
+
+        /// Day the task is on, defaults to today
+        #[arg(short, long)]
+        date: Option<String>,
+
+        /// Day to move the task to, defaults to tomorrow
+        #[arg(short, long)]
+        to: Option<String>,
+    },
+
     /// Login for syncing files
     Login,
 
     /// Sync all files
     Sync,
+
+    /// Go through files that couldn't be merged when syncing
+    Resolve,
+
+    /// Print tasks without opening the editor
+    List {
+        /// Only show tasks with this status
+        #[arg(long, value_enum)]
+        status: Option<task::Status>,
+
+        /// Only show days from this date (later.md is left out when a date range is given)
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Only show days up to this date
+        #[arg(long)]
+        to: Option<String>,
+
+        /// Only show tasks under this `##` section
+        #[arg(short, long)]
+        section: Option<String>,
+
+        /// Only show tasks containing this text
+        #[arg(short, long)]
+        text: Option<String>,
+
+        /// Only show tasks with all of these `+project`/`@context` tags
+        tags: Vec<String>,
+
+        /// Only show tasks with this tag, same as passing it as an argument
+        #[arg(long = "tag", value_name = "TAG")]
+        tag_options: Vec<String>,
+
+        /// Only show open tasks with a `due:YYYY-MM-DD` date, overdue first
+        #[arg(long)]
+        due: bool,
+
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: list::OutputFormat,
+    },
+
+    /// Search every todo file and later.md
+    Search {
+        query: String,
+
+        /// Treat the query as a regular expression
+        #[arg(short, long)]
+        regex: bool,
+
+        /// Match regardless of case
+        #[arg(short, long)]
+        ignore_case: bool,
+    },
+
+    /// Show every day a task was rolled over to and when it was completed
+    History {
+        /// Text of the task to look up
+        text: String,
+    },
+
+    /// List every `+project` and `@context` tag with its open and done counts
+    Tags,
+
+    /// Show completion stats
+    Stats {
+        /// Only include days from this date (same formats as the date argument)
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Only include days up to this date
+        #[arg(long)]
+        to: Option<String>,
+
+        /// Print stats as JSON
+        #[arg(long)]
+        json: bool,
+    },
 }
 
 fn main() {
     let cli = Cli::parse();
+    let config = config::get_config();
+
+    let mut mode = config.view.mode;
+    if cli.back.is_some() || cli.forward.is_some() {
+        mode = config::ViewMode::Days;
+    }
+
+    // everything except these commands writes to the doto directory
+    let lock = match &cli.command {
+        Some(Commands::Login)
+        | Some(Commands::List { .. })
+        | Some(Commands::Search { .. })
+        | Some(Commands::History { .. })
+        | Some(Commands::Tags)
+        | Some(Commands::Stats { .. }) => None,
+        _ => Some(lock::lock_doto_dir()),
+    };
+    if lock.is_some() {
+        todo::recover_combined();
+    }
 
     match &cli.command {
+        Some(Commands::Week { view }) => open_view(config::ViewMode::Week, &cli, &config, view),
+        Some(Commands::Month { view }) => open_view(config::ViewMode::Month, &cli, &config, view),
+        Some(Commands::Add {
+            text,
+            date,
+            section,
+            edit,
+        }) => todo::add_task(text.clone(), date.clone(), section.clone(), *edit),
+        Some(Commands::Done { task, date }) => edit::mark_done(task.clone(), date.clone()),
+        Some(Commands::Undo { task, date }) => edit::mark_undone(task.clone(), date.clone()),
+        Some(Commands::Defer { task, date, to }) => {
+            edit::defer_task(task.clone(), date.clone(), to.clone())
+        }
         Some(Commands::Login) => sync::auth::login(),
         Some(Commands::Sync) => sync::files::sync_files(),
+        Some(Commands::Resolve) => sync::conflicts::resolve_conflicts(),
+        Some(Commands::List {
+            status,
+            from,
+            to,
+            section,
+            text,
+            tags,
+            tag_options,
+            due,
+            format,
+        }) => list::list_tasks(
+            list::ListFilter {
+                status: *status,
+                from: from.clone(),
+                to: to.clone(),
+                section: section.clone(),
+                text: text.clone(),
+                tags: tags.iter().chain(tag_options).cloned().collect(),
+                due: *due,
+            },
+            *format,
+        ),
+        Some(Commands::Search {
+            query,
+            regex,
+            ignore_case,
+        }) => search::search(query.clone(), *regex, *ignore_case),
+        Some(Commands::History { text }) => history::show_history(text.clone()),
+        Some(Commands::Tags) => tags::show_tags(),
+        Some(Commands::Stats { from, to, json }) => {
+            stats::show_stats(from.clone(), to.clone(), *json)
+        }
         _ => {
             // default behaviour
             if cli.undone {
-                todo::move_undone();
+                todo::move_undone(cli.by_priority, cli.dry_run);
             } else {
                 match cli.date {
                     Some(date) => todo::open_date(date),
-                    None => todo::open_week(),
+                    None => open_view(mode, &cli, &config, &cli.view),
                 }
             }
         }
     }
 }
+
+fn open_view(mode: config::ViewMode, cli: &Cli, config: &config::Config, view: &ViewArgs) {
+    let (start, end) = todo::view_range(
+        mode,
+        cli.back.unwrap_or(config.view.back),
+        cli.forward.unwrap_or(config.view.forward),
+    );
+    let later = config.view.later && !view.no_later;
+    todo::open_week(start, end, later, &view.tags);
+}
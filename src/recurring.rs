@@ -0,0 +1,214 @@
+use crate::{
+    history::chain_key,
+    task::{DayFile, Heading, Task},
+    util::get_doto_path,
+};
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::{collections::HashMap, path::Path, sync::OnceLock};
+
+/// A line from `recurring.md` such as `every mon,thu: - [ ] standup notes`.
+///
+/// The days can be `day`, `weekday`, `weekend`, weekday names (`mon` or
+/// `monday`) or days of the month (`1,15`). Rules under a `## heading` are
+/// added to that section of the day file.
+struct Rule {
+    /// the rule line itself, used to remember which days it was added to
+    key: String,
+    days: Vec<DaySpec>,
+    task: Task,
+    section: Option<Heading>,
+}
+
+enum DaySpec {
+    Every,
+    Weekday(Weekday),
+    DayOfMonth(u32),
+}
+
+impl Rule {
+    fn parse(line: &str, section: Option<Heading>) -> Option<Rule> {
+        let rest = line.trim().strip_prefix("every ")?;
+        let (spec, task) = rest.split_once(':')?;
+        let mut days = vec![];
+        for day in spec.split(',').map(|d| d.trim().to_lowercase()) {
+            match day.as_str() {
+                "day" => days.push(DaySpec::Every),
+                "weekday" => days.extend(
+                    [
+                        Weekday::Mon,
+                        Weekday::Tue,
+                        Weekday::Wed,
+                        Weekday::Thu,
+                        Weekday::Fri,
+                    ]
+                    .map(DaySpec::Weekday),
+                ),
+                "weekend" => days.extend([Weekday::Sat, Weekday::Sun].map(DaySpec::Weekday)),
+                day => match (day.parse::<Weekday>(), day.parse::<u32>()) {
+                    (Ok(weekday), _) => days.push(DaySpec::Weekday(weekday)),
+                    (_, Ok(n)) if (1..=31).contains(&n) => days.push(DaySpec::DayOfMonth(n)),
+                    _ => {
+                        eprintln!(
+                            "Ignoring recurring task with unknown day \"{}\": {}",
+                            day, line
+                        );
+                        return None;
+                    }
+                },
+            }
+        }
+
+        let task = task.trim();
+        let mut task = Task::parse(task).unwrap_or_else(|| Task::new(task));
+        task.indent = String::new();
+        Some(Rule {
+            key: line.trim().to_string(),
+            days,
+            task,
+            section,
+        })
+    }
+
+    fn matches(&self, date: NaiveDate) -> bool {
+        self.days.iter().any(|day| match day {
+            DaySpec::Every => true,
+            DaySpec::Weekday(weekday) => date.weekday() == *weekday,
+            DaySpec::DayOfMonth(n) => date.day() == *n,
+        })
+    }
+}
+
+/// Rules are read once per run, so a broken rule is only reported once.
+fn load_rules() -> &'static [Rule] {
+    static RULES: OnceLock<Vec<Rule>> = OnceLock::new();
+    RULES.get_or_init(read_rules)
+}
+
+fn read_rules() -> Vec<Rule> {
+    let path = format!("{}/recurring.md", get_doto_path());
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return vec![];
+    };
+    let mut section = None;
+    let mut rules = vec![];
+    for line in content.lines() {
+        if let Some(heading) = Heading::parse(line) {
+            section = (heading.level > 1).then_some(heading);
+        } else if let Some(rule) = Rule::parse(line, section.clone()) {
+            rules.push(rule);
+        }
+    }
+    rules
+}
+
+/// Adds recurring tasks to the day file for `date`. Each rule is only added
+/// once per day (tracked in `.recurring.json`), so tasks removed by hand don't
+/// come back. Days before today are left alone.
+pub fn add_recurring_tasks(date: NaiveDate, path: &Path) {
+    let today = chrono::Local::now().date_naive();
+    if date < today {
+        return;
+    }
+    let rules = load_rules();
+    if rules.is_empty() {
+        return;
+    }
+
+    let state_path = format!("{}/.recurring.json", get_doto_path());
+    let mut added: HashMap<String, Vec<String>> = std::fs::read_to_string(&state_path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+    // forget days that are in the past, they won't be touched again
+    let today_str = today.format("%Y-%m-%d").to_string();
+    added.retain(|day, _| *day >= today_str);
+
+    let done = added
+        .entry(date.format("%Y-%m-%d").to_string())
+        .or_default();
+    let rules = rules
+        .iter()
+        .filter(|r| r.matches(date) && !done.contains(&r.key))
+        .collect::<Vec<_>>();
+    if rules.is_empty() {
+        return;
+    }
+
+    let mut day_file = DayFile::load(path).expect("Could not read todo file");
+    for rule in rules {
+        let key = chain_key(&rule.task.text);
+        if !day_file.tasks().any(|t| chain_key(&t.text) == key) {
+            day_file.insert_in_section(rule.section.as_ref(), vec![rule.task.clone()]);
+        }
+        done.push(rule.key.clone());
+    }
+    day_file.save(path).expect("Could not write todo file");
+
+    let state = serde_json::to_string_pretty(&added).expect("Failed to serialize recurring state");
+    std::fs::write(state_path, state).expect("Could not write recurring state");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    /// Which days of the week starting Monday 2023-05-22 a rule matches.
+    fn week(rule: &Rule) -> Vec<bool> {
+        (22..=28)
+            .map(|d| rule.matches(date(&format!("2023-05-{}", d))))
+            .collect()
+    }
+
+    #[test]
+    fn parses_every_day_spec() {
+        let rule = Rule::parse("every day: - [ ] journal", None).unwrap();
+        assert_eq!(week(&rule), [true; 7]);
+
+        let rule = Rule::parse("every weekday: - [ ] standup", None).unwrap();
+        assert_eq!(week(&rule), [true, true, true, true, true, false, false]);
+
+        let rule = Rule::parse("every weekend: - [ ] hike", None).unwrap();
+        assert_eq!(week(&rule), [false, false, false, false, false, true, true]);
+
+        let rule = Rule::parse("every Mon, thursday: - [ ] gym", None).unwrap();
+        assert_eq!(week(&rule), [true, false, false, true, false, false, false]);
+    }
+
+    #[test]
+    fn matches_days_of_the_month() {
+        let rule = Rule::parse("every 1,15: - [ ] pay bills", None).unwrap();
+        assert!(rule.matches(date("2023-06-01")));
+        assert!(rule.matches(date("2023-05-15")));
+        assert!(!rule.matches(date("2023-05-16")));
+
+        let rule = Rule::parse("every 31: - [ ] month end", None).unwrap();
+        assert!(!rule.matches(date("2023-06-30")));
+    }
+
+    #[test]
+    fn rejects_unknown_days_and_other_lines() {
+        assert!(Rule::parse("every fortnight: - [ ] x", None).is_none());
+        assert!(Rule::parse("every 32: - [ ] x", None).is_none());
+        assert!(Rule::parse("every mon, 0: - [ ] x", None).is_none());
+        assert!(Rule::parse("- [ ] not a rule", None).is_none());
+        assert!(Rule::parse("every monday - [ ] no colon", None).is_none());
+    }
+
+    #[test]
+    fn keeps_the_task_and_section() {
+        let section = Heading::parse("## work");
+        let rule = Rule::parse("  every fri: - [ ] timesheet", section).unwrap();
+        assert_eq!(rule.key, "every fri: - [ ] timesheet");
+        assert_eq!(rule.task.to_string(), "- [ ] timesheet");
+        assert_eq!(rule.section.unwrap().title, "work");
+
+        let rule = Rule::parse("every fri: plain text", None).unwrap();
+        assert_eq!(rule.task.to_string(), "- [ ] plain text");
+        assert!(rule.section.is_none());
+    }
+}
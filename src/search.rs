@@ -0,0 +1,109 @@
+use crate::{
+    lock::lock_doto_dir,
+    task::{date_from_path, Heading},
+    todo::{get_all_files, open_file_at},
+    util::{ask, get_doto_path},
+};
+
+use regex::RegexBuilder;
+use std::{io::IsTerminal, path::PathBuf};
+
+struct SearchResult {
+    /// file name without ".md"
+    file: String,
+    section: Option<String>,
+    /// 1-based line number in the file
+    line_number: usize,
+    line: String,
+}
+
+pub fn search(query: String, use_regex: bool, ignore_case: bool) {
+    let pattern = if use_regex {
+        query.clone()
+    } else {
+        regex::escape(&query)
+    };
+    let regex = match RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+    {
+        Ok(regex) => regex,
+        Err(err) => {
+            eprintln!("Invalid search pattern: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut files = get_all_files()
+        .into_iter()
+        .filter_map(|f| date_from_path(&f).map(|date| (date, f)))
+        .collect::<Vec<_>>();
+    files.sort();
+    let mut files = files
+        .into_iter()
+        .map(|(date, f)| (date.format("%Y-%m-%d").to_string(), f))
+        .collect::<Vec<_>>();
+    let later_path = PathBuf::from(format!("{}/later.md", get_doto_path()));
+    if later_path.exists() {
+        files.push(("later".to_string(), later_path));
+    }
+
+    let mut results = vec![];
+    for (file, path) in files {
+        let content = std::fs::read_to_string(&path).expect("Could not read todo file");
+        let mut section = None;
+        for (i, line) in content.lines().enumerate() {
+            if let Some(heading) = Heading::parse(line) {
+                section = (heading.level > 1).then(|| heading.title.trim().to_string());
+            }
+            if regex.is_match(line) {
+                results.push(SearchResult {
+                    file: file.clone(),
+                    section: section.clone(),
+                    line_number: i + 1,
+                    line: line.to_string(),
+                });
+            }
+        }
+    }
+
+    if results.is_empty() {
+        println!("No matches for \"{}\"", query);
+        return;
+    }
+
+    let mut file: Option<&str> = None;
+    let mut section: Option<&str> = None;
+    for (n, result) in results.iter().enumerate() {
+        if file != Some(result.file.as_str()) {
+            if file.is_some() {
+                println!();
+            }
+            println!("# {}", result.file);
+            file = Some(&result.file);
+            section = None;
+        }
+        if section != result.section.as_deref() {
+            if let Some(title) = &result.section {
+                println!("## {}", title);
+            }
+            section = result.section.as_deref();
+        }
+        println!("{:>3}) {}", n + 1, result.line.trim());
+    }
+
+    if !std::io::stdin().is_terminal() {
+        return;
+    }
+    let answer = ask("\nOpen a result in your editor (number, or enter to skip)?");
+    let Some(result) = answer
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_sub(1))
+        .and_then(|n| results.get(n))
+    else {
+        return;
+    };
+    let _lock = lock_doto_dir();
+    open_file_at(result.file.clone(), Some(result.line_number));
+}
@@ -0,0 +1,197 @@
+use crate::{
+    task::{date_from_path, DayFile, Status, Task},
+    todo::{get_all_files, parse_day_string},
+};
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+struct Counts {
+    completed: usize,
+    open: usize,
+    rescheduled: usize,
+}
+
+impl Counts {
+    fn add(&mut self, task: &Task) {
+        match task.status {
+            Status::Completed => self.completed += 1,
+            Status::Open => self.open += 1,
+            Status::Rescheduled => self.rescheduled += 1,
+        }
+    }
+
+    fn total(&self) -> usize {
+        self.completed + self.open + self.rescheduled
+    }
+
+    fn all_done(&self) -> bool {
+        self.total() > 0 && self.completed == self.total()
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Stats {
+    total: Counts,
+    days: BTreeMap<String, Counts>,
+    weeks: BTreeMap<String, Counts>,
+    sections: BTreeMap<String, Counts>,
+    current_streak: usize,
+    longest_streak: usize,
+    average_reschedules: f64,
+}
+
+pub fn show_stats(from: Option<String>, to: Option<String>, json: bool) {
+    let from = from.map(|d| parse_day_string(d).expect("Invalid --from date"));
+    let to = to.map(|d| parse_day_string(d).expect("Invalid --to date"));
+
+    let mut files = get_all_files()
+        .into_iter()
+        .filter_map(|f| date_from_path(&f).map(|date| (date, f)))
+        .filter(|(date, _)| from.is_none_or(|from| *date >= from))
+        .filter(|(date, _)| to.is_none_or(|to| *date <= to))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut total = Counts::default();
+    let mut days = BTreeMap::new();
+    let mut weeks: BTreeMap<String, Counts> = BTreeMap::new();
+    let mut sections: BTreeMap<String, Counts> = BTreeMap::new();
+
+    for (date, file) in files {
+        let day_file = DayFile::load(&file).expect("Could not read todo file");
+        let mut day = Counts::default();
+        for task in day_file.tasks() {
+            total.add(task);
+            day.add(task);
+            weeks
+                .entry(date.format("%G-W%V").to_string())
+                .or_default()
+                .add(task);
+            let section = task.section.clone().unwrap_or("(none)".to_string());
+            sections.entry(section).or_default().add(task);
+        }
+        days.insert(date.format("%Y-%m-%d").to_string(), day);
+    }
+
+    let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+    let (current_streak, longest_streak) = streaks(&days, &today);
+
+    // every `- [>]` line is one reschedule of a task that ends up open or completed
+    let finished = total.completed + total.open;
+    let average_reschedules = if finished > 0 {
+        total.rescheduled as f64 / finished as f64
+    } else {
+        0.0
+    };
+
+    let stats = Stats {
+        total,
+        days,
+        weeks,
+        sections,
+        current_streak,
+        longest_streak,
+        average_reschedules,
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&stats).expect("Failed to serialize stats")
+        );
+    } else {
+        print_stats(&stats);
+    }
+}
+
+/// Current and longest run of days with every task done, up to `today`. Days
+/// without any tasks neither extend nor break a streak, and neither does today
+/// while it still has open tasks.
+fn streaks(days: &BTreeMap<String, Counts>, today: &str) -> (usize, usize) {
+    let mut current_streak = 0;
+    let mut longest_streak = 0;
+    for (date, counts) in days.range(..=today.to_string()) {
+        if counts.total() == 0 || (date == today && !counts.all_done()) {
+            continue;
+        }
+        if counts.all_done() {
+            current_streak += 1;
+            longest_streak = longest_streak.max(current_streak);
+        } else {
+            current_streak = 0;
+        }
+    }
+    (current_streak, longest_streak)
+}
+
+fn print_stats(stats: &Stats) {
+    println!("# days");
+    for (date, counts) in &stats.days {
+        print_counts(date, counts);
+    }
+    println!("\n# weeks");
+    for (week, counts) in &stats.weeks {
+        print_counts(week, counts);
+    }
+    println!("\n# sections");
+    for (section, counts) in &stats.sections {
+        print_counts(section, counts);
+    }
+    println!();
+    print_counts("total", &stats.total);
+    println!("current streak: {} days", stats.current_streak);
+    println!("longest streak: {} days", stats.longest_streak);
+    println!(
+        "average reschedules per task: {:.2}",
+        stats.average_reschedules
+    );
+}
+
+fn print_counts(label: &str, counts: &Counts) {
+    println!(
+        "{:<12} {:>4} done {:>4} open {:>4} rescheduled",
+        label, counts.completed, counts.open, counts.rescheduled
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(completed: usize, open: usize) -> Counts {
+        Counts {
+            completed,
+            open,
+            rescheduled: 0,
+        }
+    }
+
+    fn days(days: &[(&str, Counts)]) -> BTreeMap<String, Counts> {
+        days.iter().map(|(d, c)| (d.to_string(), *c)).collect()
+    }
+
+    #[test]
+    fn counts_streaks_up_to_today() {
+        let days = days(&[
+            ("2023-05-20", counts(2, 0)),
+            ("2023-05-21", counts(1, 1)),
+            ("2023-05-22", counts(1, 0)),
+            ("2023-05-23", counts(0, 0)),
+            ("2023-05-24", counts(3, 0)),
+            // open recurring tasks in days to come
+            ("2023-05-25", counts(0, 2)),
+        ]);
+        assert_eq!(streaks(&days, "2023-05-24"), (2, 2));
+        assert_eq!(streaks(&days, "2023-05-25"), (2, 2));
+        assert_eq!(streaks(&days, "2023-05-21"), (1, 1));
+    }
+
+    #[test]
+    fn open_tasks_today_dont_break_the_streak_yet() {
+        let days = days(&[("2023-05-23", counts(1, 0)), ("2023-05-24", counts(1, 1))]);
+        assert_eq!(streaks(&days, "2023-05-24"), (1, 1));
+        assert_eq!(streaks(&days, "2023-05-25"), (0, 1));
+    }
+}
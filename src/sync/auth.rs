@@ -1,7 +1,7 @@
 use indicatif::ProgressBar;
 use reqwest::StatusCode;
 use serde_json::{json, to_writer_pretty};
-use std::{fs::File, path::PathBuf};
+use std::fs::File;
 
 pub fn login() {
     let challenge = new_challenge().expect("Failed to get challenge");
@@ -124,7 +124,7 @@ fn save_access_token(token: String) {
     // save the token to a config file in ~/.config/doto.json
     // let mut config_path = dirs::home_dir().expect("Could not determine home directory");
     if let Some(home_path) = dirs::home_dir() {
-        let mut config_path = PathBuf::from(home_path);
+        let mut config_path = home_path;
         config_path.push(".config/doto.json");
         println!("Saving config to {:?}", config_path);
         let mut config_file = File::create(config_path).expect("Failed to create config file");
@@ -138,7 +138,7 @@ fn save_access_token(token: String) {
 
 pub fn get_access_token() -> Option<String> {
     if let Some(home_path) = dirs::home_dir() {
-        let mut config_path = PathBuf::from(home_path);
+        let mut config_path = home_path;
         config_path.push(".config/doto.json");
         if config_path.exists() {
             let config_file = File::open(config_path).expect("Failed to open config file");
@@ -0,0 +1,148 @@
+use crate::{
+    editor::open_in_editor,
+    util::{ask, confirm, get_doto_path},
+};
+
+use similar::TextDiff;
+use std::path::{Path, PathBuf};
+
+use super::merge::conflict_markers;
+
+const CONFLICT_MARKER: &str = ".conflict-";
+
+fn host_name() -> String {
+    gethostname::gethostname()
+        .to_string_lossy()
+        .split('.')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or("local")
+        .to_string()
+}
+
+/// A free `2023-05-24.conflict-<host>.md` path to keep this machine's version
+/// of `file` in when sync couldn't merge it.
+pub fn new_conflict_path(file: &Path) -> PathBuf {
+    let stem = file.file_stem().unwrap().to_str().unwrap();
+    let name = format!("{}{}{}", stem, CONFLICT_MARKER, host_name());
+    let mut n = 1;
+    loop {
+        let suffix = if n == 1 {
+            String::new()
+        } else {
+            format!("-{}", n)
+        };
+        let path = file.with_file_name(format!("{}{}.md", name, suffix));
+        if !path.exists() {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+pub fn is_conflict_file(file_name: &str) -> bool {
+    file_name.contains(CONFLICT_MARKER)
+}
+
+/// `(file, conflicting version)` pairs left by sync, oldest day first.
+fn find_conflicts() -> Vec<(PathBuf, PathBuf)> {
+    let doto_path = PathBuf::from(get_doto_path());
+    let mut conflicts = std::fs::read_dir(&doto_path)
+        .expect("Could not read doto directory")
+        .filter_map(|f| f.ok())
+        .filter_map(|f| f.file_name().to_str().map(|name| name.to_string()))
+        .filter_map(|name| {
+            let (stem, _) = name.split_once(CONFLICT_MARKER)?;
+            name.ends_with(".md").then(|| {
+                (
+                    doto_path.join(format!("{}.md", stem)),
+                    doto_path.join(&name),
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    conflicts.sort();
+    conflicts
+}
+
+/// Goes through the conflicting versions sync saved, showing how each differs
+/// from the synced file and keeping one of them or a merge made in the editor.
+pub fn resolve_conflicts() {
+    let conflicts = find_conflicts();
+    if conflicts.is_empty() {
+        println!("No conflicts to resolve.");
+        return;
+    }
+
+    for (file, conflict) in conflicts {
+        let file_name = file.file_name().unwrap().to_str().unwrap();
+        let conflict_name = conflict.file_name().unwrap().to_str().unwrap();
+        let synced = std::fs::read_to_string(&file).unwrap_or_default();
+        let ours = std::fs::read_to_string(&conflict).expect("Could not read conflict file");
+
+        println!();
+        print!(
+            "{}",
+            TextDiff::from_lines(synced.as_str(), ours.as_str())
+                .unified_diff()
+                .header(file_name, conflict_name)
+        );
+
+        loop {
+            let answer = ask(&format!(
+                "Keep [1] {} (synced), [2] {}, [e]dit a merge or [s]kip?",
+                file_name, conflict_name
+            ));
+            match answer.as_str() {
+                "1" => {}
+                "2" => std::fs::write(&file, &ours).expect("Could not write todo file"),
+                "e" | "edit" => {
+                    let merged = conflict_markers(&ours, &synced, conflict_name, file_name);
+                    if !edit_merge(&file, &merged) {
+                        break;
+                    }
+                }
+                "s" | "skip" | "" => break,
+                _ => continue,
+            }
+            std::fs::remove_file(&conflict).expect("Could not remove conflict file");
+            println!("Resolved {}", file_name);
+            break;
+        }
+    }
+}
+
+/// Opens a merge with conflict markers in the editor until none are left,
+/// then saves it to `file`. Returns false if the user gave up.
+fn edit_merge(file: &Path, merged: &str) -> bool {
+    let merge_path = file.with_file_name(format!(
+        ".{}.merge.md",
+        file.file_stem().unwrap().to_str().unwrap()
+    ));
+    std::fs::write(&merge_path, merged).expect("Could not write merge file");
+    let mut marker = first_marker(merged);
+    loop {
+        open_in_editor(merge_path.to_str().unwrap(), marker.map(|i| i + 1));
+        let content = std::fs::read_to_string(&merge_path).expect("Could not read merge file");
+        marker = first_marker(&content);
+        if marker.is_none() {
+            std::fs::write(file, content).expect("Could not write todo file");
+            std::fs::remove_file(&merge_path).expect("Could not remove merge file");
+            return true;
+        }
+        if !confirm(
+            "There are still conflict markers, re-open the editor?",
+            true,
+        ) {
+            std::fs::remove_file(&merge_path).expect("Could not remove merge file");
+            return false;
+        }
+    }
+}
+
+/// Index of the first line of a conflict marker.
+fn first_marker(content: &str) -> Option<usize> {
+    content.lines().position(|line| {
+        line.starts_with("<<<<<<< ") || line == "=======" || line.starts_with(">>>>>>> ")
+    })
+}
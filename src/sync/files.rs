@@ -1,116 +1,386 @@
-use chrono::DateTime;
 use std::{
+    collections::HashMap,
+    fmt,
     io::Write,
-    path::{self, PathBuf},
-    time::SystemTime,
+    path::{self, Path, PathBuf},
+    sync::{mpsc, Mutex},
 };
 
-use indicatif::ProgressBar;
+use indicatif::{MultiProgress, ProgressBar};
+use reqwest::{blocking::Client, StatusCode};
 use serde::Deserialize;
 use sha2::{Digest, Sha256};
 
+use super::{
+    conflicts::new_conflict_path,
+    merge::merge,
+    state::{read_base, SyncState},
+};
+use crate::util::confirm;
+
+/// Deleting more files than this in one sync has to be confirmed.
+const MAX_UNCONFIRMED_DELETIONS: usize = 5;
+
 pub fn sync_files() {
     let Some(access_token) = crate::sync::auth::get_access_token() else {
         println!("No access token found, please login");
         return;
     };
-    let todo_files = crate::todo::get_all_files();
+    let todo_dir = path::PathBuf::from(crate::util::get_doto_path());
+    let mut state = SyncState::load();
 
-    let uploaded = list_uploaded_files(&access_token).expect("Failed to list uploaded files");
+    // one client for every request, so connections to the server are reused
+    let client = Client::new();
+    let uploaded =
+        list_uploaded_files(&client, &access_token).expect("Failed to list uploaded files");
 
     // transform into a map for easy lookup
-    let uploaded_files: std::collections::HashMap<String, (String, SystemTime)> = uploaded
+    let uploaded_files: HashMap<String, UploadedFile> = uploaded
         .files
         .into_iter()
-        .map(|f| (f.name, (f.hash, parse_js_date(&f.last_modified))))
+        .map(|f| (f.name.clone(), f))
         .collect();
 
-    let modified_todo_files = todo_files
+    // every file that is here, on the server or was there at the last sync
+    let mut filenames = crate::todo::get_all_files()
         .iter()
-        .filter(|file| {
-            let last_modified = get_last_modified_time(&file).expect("Failed to get last modified");
-            if let Some(uploaded_file) =
-                uploaded_files.get(file.file_name().unwrap().to_str().unwrap())
-            {
-                if hash_file(&file) == *uploaded_file.0 {
-                    return false;
-                }
+        .map(|f| f.file_name().unwrap().to_str().unwrap().to_string())
+        .chain(uploaded_files.keys().cloned())
+        .chain(state.files.keys().cloned())
+        .collect::<Vec<_>>();
+    filenames.sort();
+    filenames.dedup();
 
-                // Server version is more recent
-                if last_modified < uploaded_file.1 {
-                    return false;
-                }
+    // each side changed if its hash differs from the last sync, files changed
+    // on both sides are merged with the version both started from as the base
+    let mut transfers = vec![];
+    let mut files_to_delete_locally = vec![];
+    for filename in filenames {
+        let file = todo_dir.join(&filename);
+        let local = file.exists().then(|| hash_file(&file));
+        let server = uploaded_files.get(&filename);
+        let synced = state.files.get(&filename).map(|f| f.hash.clone());
 
-                println!("Local version more recent: {:?}", file);
+        match (local, server, synced) {
+            (Some(local), Some(server), synced) if local == server.hash => {
+                let revision = Some(server.last_modified.clone());
+                if synced.as_ref() != Some(&local) || state.files[&filename].revision != revision {
+                    state.synced(&file, local, revision);
+                }
             }
-            true
-        })
-        .map(|f| f.to_path_buf())
-        .collect::<Vec<PathBuf>>();
-
-    let mut files_to_download = Vec::new();
-    let todo_dir = path::PathBuf::from(crate::util::get_doto_path());
-    for (filename, (hash, _)) in uploaded_files.iter() {
-        let file_path = todo_dir.join(filename);
-        if !modified_todo_files.contains(&file_path) {
-            if !file_path.exists() || hash_file(&file_path) != *hash {
-                files_to_download.push(file_path.clone());
-                println!("Server file more recent: {:?}", file_path)
+            (Some(_), Some(server), Some(synced)) if synced == server.hash => {
+                println!("Local version changed: {:?}", file);
+                transfers.push(Transfer::Upload(file));
+            }
+            (Some(local), Some(_), Some(synced)) if synced == local => {
+                println!("Server version changed: {:?}", file);
+                transfers.push(Transfer::Download(file));
             }
+            // without a base there's no telling which side changed
+            (Some(_), Some(_), _) => transfers.push(Transfer::Merge(file)),
+            (Some(local), None, Some(synced)) if synced == local => {
+                files_to_delete_locally.push(file)
+            }
+            (Some(_), None, Some(_)) => {
+                println!("Deleted on the server but changed here: {:?}", file);
+                transfers.push(Transfer::Upload(file));
+            }
+            (Some(_), None, None) => transfers.push(Transfer::Upload(file)),
+            (None, Some(server), Some(synced)) if synced == server.hash => {
+                transfers.push(Transfer::DeleteOnServer(file))
+            }
+            (None, Some(_), Some(_)) => {
+                println!("Deleted here but changed on the server: {:?}", file);
+                transfers.push(Transfer::Download(file));
+            }
+            (None, Some(_), None) => transfers.push(Transfer::Download(file)),
+            // gone on both sides
+            (None, None, _) => state.forget(&file),
         }
     }
 
-    // TODO:
-    // - more robust comparison of modified time ... currently always uploads / downloads because of upload time
-    //  - consider content hash?
-    // - handle deleted files
-    // - handle new files on server
-    // - parrallelize upload / download
+    let deletions = files_to_delete_locally.len()
+        + transfers
+            .iter()
+            .filter(|t| matches!(t, Transfer::DeleteOnServer(_)))
+            .count();
+    if deletions > MAX_UNCONFIRMED_DELETIONS {
+        println!("This sync would delete {} files:", deletions);
+        for file in &files_to_delete_locally {
+            println!("  {} (deleted on the server)", file.display());
+        }
+        for transfer in &transfers {
+            if let Transfer::DeleteOnServer(file) = transfer {
+                println!("  {} on the server (deleted here)", file.display());
+            }
+        }
+        if !confirm("Delete them?", false) {
+            println!("Skipping deletions, they'll be asked about again next sync.");
+            files_to_delete_locally.clear();
+            transfers.retain(|t| !matches!(t, Transfer::DeleteOnServer(_)));
+        }
+    }
 
-    let total_files: u64 = (modified_todo_files.len() + files_to_download.len()) as u64;
+    let total_files: u64 = (transfers.len() + files_to_delete_locally.len()) as u64;
 
     if total_files == 0 {
+        state.save();
         println!("Up to date.");
         return;
     }
 
-    let pb = ProgressBar::new(total_files);
-    for file in &modified_todo_files {
-        upload_file(file, &access_token).expect("Failed to upload file");
+    let multi = MultiProgress::new();
+    let pb = multi.add(ProgressBar::new(total_files));
+    for file in files_to_delete_locally {
         pb.inc(1);
+        pb.println(format!("Deleted on the server: {:?}", file));
+        std::fs::remove_file(&file).expect("Failed to delete file");
+        state.forget(&file);
     }
 
-    for file in files_to_download {
-        pb.inc(1);
-        let downloaded = download_file(&file, &access_token).expect("Failed to download file");
-        let mut editable_file = std::fs::File::create(file).expect("Failed to create file");
-        editable_file
-            .write(downloaded.as_bytes())
-            .expect("Failed to write file");
-    }
+    // workers take transfers off a shared queue, each showing its current file
+    // on a line of its own, while this thread records what they did
+    let concurrency = crate::config::get_config().sync.concurrency.max(1);
+    let workers = concurrency.min(transfers.len());
+    let queue = Mutex::new(transfers.into_iter());
+    let (done, outcomes) = mpsc::channel();
+    let mut conflicts = vec![];
+    let mut failures = vec![];
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            let status = multi.add(ProgressBar::new_spinner());
+            let (done, queue, pb) = (done.clone(), &queue, &pb);
+            let (client, access_token, uploaded_files) = (&client, &access_token, &uploaded_files);
+            scope.spawn(move || {
+                status.enable_steady_tick(std::time::Duration::from_millis(120));
+                loop {
+                    let Some(transfer) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    let name = transfer.to_string();
+                    status.set_message(name.clone());
+                    let outcome = transfer.run(client, access_token, uploaded_files, pb);
+                    done.send((name, outcome)).unwrap();
+                }
+                status.finish_and_clear();
+            });
+        }
+        drop(done);
+
+        // the state only changes for what the server confirmed, anything that
+        // failed is found changed again and retried next sync
+        for (name, outcome) in outcomes {
+            pb.inc(1);
+            let outcome = match outcome {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    failures.push(format!("{}: {}", name, err));
+                    continue;
+                }
+            };
+            match outcome {
+                Outcome::Synced { file, revision } => {
+                    state.synced(&file, hash_file(&file), revision)
+                }
+                Outcome::Deleted(file) => state.forget(&file),
+                // the file stays in the state, so it isn't downloaded again
+                Outcome::DeleteUnsupported(status) => failures.push(format!(
+                    "{}: the server can't delete files ({}), delete it there or restore it here",
+                    name, status
+                )),
+                Outcome::Conflict {
+                    file,
+                    revision,
+                    conflict,
+                } => {
+                    state.synced(&file, hash_file(&file), revision);
+                    conflicts.push(conflict);
+                }
+            }
+        }
+    });
+    state.save();
     pb.finish_with_message("Sync complete.");
+
+    if !conflicts.is_empty() {
+        println!("These files were changed here and on the server in the same places,");
+        println!("the server version was kept and yours saved next to it:");
+        for conflict in conflicts {
+            println!("  {}", conflict.display());
+        }
+        println!("Run `doto resolve` to go through them.");
+    }
+
+    if !failures.is_empty() {
+        eprintln!("These files failed to sync and will be tried again next sync:");
+        for failure in failures {
+            eprintln!("  {}", failure);
+        }
+        std::process::exit(1);
+    }
+}
+
+/// Work on the server for one file, run alongside the others.
+enum Transfer {
+    Upload(PathBuf),
+    Download(PathBuf),
+    Merge(PathBuf),
+    DeleteOnServer(PathBuf),
 }
 
-fn upload_file(file_path: &PathBuf, access_token: &String) -> Result<(), reqwest::Error> {
-    let client = reqwest::blocking::Client::new();
+/// What a transfer did, to be recorded in the sync state.
+enum Outcome {
+    Synced {
+        file: PathBuf,
+        revision: Option<String>,
+    },
+    Deleted(PathBuf),
+    /// the server has no way to delete files and answered with this status
+    DeleteUnsupported(StatusCode),
+    Conflict {
+        file: PathBuf,
+        revision: Option<String>,
+        conflict: PathBuf,
+    },
+}
 
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (action, file) = match self {
+            Transfer::Upload(file) => ("Uploading", file),
+            Transfer::Download(file) => ("Downloading", file),
+            Transfer::Merge(file) => ("Merging", file),
+            Transfer::DeleteOnServer(file) => ("Deleting", file),
+        };
+        write!(
+            f,
+            "{} {}",
+            action,
+            file.file_name().unwrap().to_str().unwrap()
+        )
+    }
+}
+
+impl Transfer {
+    fn run(
+        self,
+        client: &Client,
+        access_token: &String,
+        uploaded_files: &HashMap<String, UploadedFile>,
+        pb: &ProgressBar,
+    ) -> Result<Outcome, reqwest::Error> {
+        let revision = |file: &Path| {
+            uploaded_files
+                .get(file.file_name().unwrap().to_str().unwrap())
+                .map(|f| f.last_modified.clone())
+        };
+        match self {
+            Transfer::Upload(file) => {
+                upload_file(client, &file, access_token)?;
+                Ok(Outcome::Synced {
+                    file,
+                    revision: None,
+                })
+            }
+            Transfer::Download(file) => {
+                let downloaded = download_file(client, &file, access_token)?;
+                write_file(&file, &downloaded);
+                let revision = revision(&file);
+                Ok(Outcome::Synced { file, revision })
+            }
+            Transfer::DeleteOnServer(file) => match delete_file(client, &file, access_token)? {
+                Some(status) => Ok(Outcome::DeleteUnsupported(status)),
+                None => {
+                    pb.println(format!("Deleted here: {:?}", file));
+                    Ok(Outcome::Deleted(file))
+                }
+            },
+            Transfer::Merge(file) => {
+                let server = download_file(client, &file, access_token)?;
+                let local = std::fs::read_to_string(&file).expect("Failed to read file");
+                let mut revision = revision(&file);
+                match merge_versions(read_base(&file), &local, &server) {
+                    Some(merged) => {
+                        pb.println(format!("Merged changes from both sides: {:?}", file));
+                        write_file(&file, &merged);
+                        if merged != server {
+                            upload_file(client, &file, access_token)?;
+                            revision = None;
+                        }
+                        Ok(Outcome::Synced { file, revision })
+                    }
+                    None => {
+                        // keep the server version and put ours next to it
+                        let conflict = new_conflict_path(&file);
+                        std::fs::rename(&file, &conflict).expect("Failed to save conflicting file");
+                        write_file(&file, &server);
+                        Ok(Outcome::Conflict {
+                            file,
+                            revision,
+                            conflict,
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The merge of both versions of a file, or `None` if it has to be resolved by
+/// hand. Without a base there's no telling which side changed a line, so both
+/// versions are kept as they are.
+fn merge_versions(base: Option<String>, local: &str, server: &str) -> Option<String> {
+    merge(&base?, local, server)
+}
+
+fn write_file(file: &Path, content: &str) {
+    let mut editable_file = std::fs::File::create(file).expect("Failed to create file");
+    editable_file
+        .write_all(content.as_bytes())
+        .expect("Failed to write file");
+}
+
+fn upload_file(
+    client: &Client,
+    file_path: &PathBuf,
+    access_token: &String,
+) -> Result<(), reqwest::Error> {
     let form = reqwest::blocking::multipart::Form::new()
         .file("file", file_path)
         .expect("Failed to read file");
 
     client
-        .post(&format!("{}/sync/file", super::constants::BASE_URL))
+        .post(format!("{}/sync/file", super::constants::BASE_URL))
         .bearer_auth(access_token)
         .multipart(form)
-        .send()?;
+        .send()?
+        .error_for_status()?;
 
     Ok(())
 }
 
-fn get_last_modified_time(file: &PathBuf) -> Result<SystemTime, std::io::Error> {
-    let metadata = std::fs::metadata(file)?;
-    let modified_time = metadata.modified()?;
-    Ok(modified_time)
+/// Deletes a file the server just listed. Returns the status if the server
+/// doesn't know `DELETE /sync/file/<name>`, which older servers don't.
+fn delete_file(
+    client: &Client,
+    file: &Path,
+    access_token: &String,
+) -> Result<Option<StatusCode>, reqwest::Error> {
+    let response = client
+        .delete(format!(
+            "{}/sync/file/{}",
+            super::constants::BASE_URL,
+            file.file_name().unwrap().to_str().unwrap()
+        ))
+        .bearer_auth(access_token)
+        .send()?;
+    match response.status() {
+        status @ (StatusCode::NOT_FOUND | StatusCode::METHOD_NOT_ALLOWED) => Ok(Some(status)),
+        _ => {
+            response.error_for_status()?;
+            Ok(None)
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -121,16 +391,20 @@ struct ListUploadedFilesResponse {
 #[derive(Deserialize, Debug)]
 struct UploadedFile {
     name: String,
+    /// used as the server's revision of the file, only ever compared for equality
     last_modified: String,
     hash: String,
 }
 
-fn list_uploaded_files(access_token: &String) -> Result<ListUploadedFilesResponse, reqwest::Error> {
-    let client = reqwest::blocking::Client::new();
+fn list_uploaded_files(
+    client: &Client,
+    access_token: &String,
+) -> Result<ListUploadedFilesResponse, reqwest::Error> {
     let res: ListUploadedFilesResponse = client
-        .get(&format!("{}/sync/files", super::constants::BASE_URL))
+        .get(format!("{}/sync/files", super::constants::BASE_URL))
         .bearer_auth(access_token)
         .send()?
+        .error_for_status()?
         .json()?;
 
     Ok(res) // TODO: handle errors
@@ -141,37 +415,65 @@ struct DownloadedFile {
     content: String,
 }
 
-fn download_file(file: &PathBuf, access_token: &String) -> Result<String, reqwest::Error> {
-    let client = reqwest::blocking::Client::new();
+fn download_file(
+    client: &Client,
+    file: &Path,
+    access_token: &String,
+) -> Result<String, reqwest::Error> {
     let res: DownloadedFile = client
-        .get(&format!(
+        .get(format!(
             "{}/sync/download/{}",
             super::constants::BASE_URL,
             file.file_name().unwrap().to_str().unwrap()
         ))
         .bearer_auth(access_token)
         .send()?
+        .error_for_status()?
         .json()?;
     Ok(res.content)
 }
 
-fn parse_js_date(js_date: &String) -> SystemTime {
-    let dt = DateTime::parse_from_rfc3339(js_date).unwrap();
-    let ts = dt.timestamp();
-    SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(ts as u64)
+fn hash_file(file_path: &PathBuf) -> String {
+    let file_contents = std::fs::read_to_string(file_path)
+        .unwrap_or_else(|_| panic!("Could not read file contents: {:?}", file_path));
+    hash_content(&file_contents)
 }
 
-fn hash_file(file_path: &PathBuf) -> String {
+fn hash_content(content: &str) -> String {
     let mut hasher = Sha256::new();
-
-    let file_contents = std::fs::read_to_string(file_path)
-        .expect(format!("Could not read file contents: {:?}", file_path).as_str());
-    hasher.update(file_contents);
+    hasher.update(content);
 
     let result = hasher.finalize();
 
-    return result
+    result
         .iter()
         .map(|b| format!("{:02x}", b))
-        .collect::<String>();
+        .collect::<String>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_against_the_base() {
+        let base = "# d\n- [ ] a\n- [ ] b\n".to_string();
+        let local = "# d\n- [x] a\n- [ ] b\n";
+        let server = "# d\n- [ ] a\n- [x] b\n";
+        assert_eq!(
+            merge_versions(Some(base), local, server).unwrap(),
+            "# d\n- [x] a\n- [x] b\n"
+        );
+    }
+
+    #[test]
+    fn conflicts_without_a_base() {
+        let local = "# d\n- [x] a\n- [ ] b\n";
+        let server = "# d\n- [ ] a\n- [ ] b\n";
+        assert_eq!(merge_versions(None, local, server), None);
+        assert_eq!(
+            merge_versions(None, "# d\n- [ ] a\n", "# d\n- [ ] b\n"),
+            None
+        );
+    }
 }
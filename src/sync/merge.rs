@@ -0,0 +1,259 @@
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+
+/// A change one side made to the base: `base[start..end]` became `lines`.
+#[derive(Debug)]
+struct Hunk<'a> {
+    start: usize,
+    end: usize,
+    lines: &'a [&'a str],
+}
+
+fn hunks<'a>(base: &[&str], changed: &'a [&'a str]) -> Vec<Hunk<'a>> {
+    capture_diff_slices(Algorithm::Myers, base, changed)
+        .into_iter()
+        .filter_map(|op| {
+            let (start, old_len, new_index, new_len) = match op {
+                DiffOp::Equal { .. } => return None,
+                DiffOp::Delete {
+                    old_index,
+                    old_len,
+                    new_index,
+                } => (old_index, old_len, new_index, 0),
+                DiffOp::Insert {
+                    old_index,
+                    new_index,
+                    new_len,
+                } => (old_index, 0, new_index, new_len),
+                DiffOp::Replace {
+                    old_index,
+                    old_len,
+                    new_index,
+                    new_len,
+                } => (old_index, old_len, new_index, new_len),
+            };
+            Some(Hunk {
+                start,
+                end: start + old_len,
+                lines: &changed[new_index..new_index + new_len],
+            })
+        })
+        .collect()
+}
+
+/// `base[start..end]` with `hunks` (which all lie inside it) applied.
+fn apply<'a>(base: &[&'a str], start: usize, end: usize, hunks: &[&Hunk<'a>]) -> Vec<&'a str> {
+    let mut lines = vec![];
+    let mut pos = start;
+    for hunk in hunks {
+        lines.extend(&base[pos..hunk.start]);
+        lines.extend(hunk.lines);
+        pos = hunk.end;
+    }
+    lines.extend(&base[pos..end]);
+    lines
+}
+
+/// Line based three-way merge of two versions of a file that both started
+/// from `base`. Changes to different lines are combined, as are lines both
+/// sides added at the same place (ours first). Returns `None` if both sides
+/// changed the same lines differently.
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Option<String> {
+    let base_lines = base.lines().collect::<Vec<_>>();
+    let our_lines = ours.lines().collect::<Vec<_>>();
+    let their_lines = theirs.lines().collect::<Vec<_>>();
+    let our_hunks = hunks(&base_lines, &our_lines);
+    let their_hunks = hunks(&base_lines, &their_lines);
+
+    let mut merged: Vec<&str> = vec![];
+    let mut pos = 0;
+    let (mut i, mut j) = (0, 0);
+    while i < our_hunks.len() || j < their_hunks.len() {
+        // start from the earliest hunk, lines added before a change go first
+        let ours_first = match (our_hunks.get(i), their_hunks.get(j)) {
+            (Some(a), Some(b)) => (a.start, a.end) <= (b.start, b.end),
+            (a, _) => a.is_some(),
+        };
+        let mut ours_in = vec![];
+        let mut theirs_in = vec![];
+        let seed = if ours_first {
+            i += 1;
+            ours_in.push(&our_hunks[i - 1]);
+            &our_hunks[i - 1]
+        } else {
+            j += 1;
+            theirs_in.push(&their_hunks[j - 1]);
+            &their_hunks[j - 1]
+        };
+        let (start, mut end) = (seed.start, seed.end);
+
+        // then collect the hunks from both sides that touch the same lines
+        loop {
+            let overlaps = |h: &Hunk| {
+                (h.start < end && h.end > start)
+                    || (h.start == h.end && start == end && h.start == start)
+            };
+            if let Some(h) = our_hunks.get(i).filter(|h| overlaps(h)) {
+                end = end.max(h.end);
+                ours_in.push(h);
+                i += 1;
+            } else if let Some(h) = their_hunks.get(j).filter(|h| overlaps(h)) {
+                end = end.max(h.end);
+                theirs_in.push(h);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+
+        merged.extend(&base_lines[pos..start]);
+        let our_version = apply(&base_lines, start, end, &ours_in);
+        let their_version = apply(&base_lines, start, end, &theirs_in);
+        if theirs_in.is_empty() || our_version == their_version {
+            merged.extend(our_version);
+        } else if ours_in.is_empty() {
+            merged.extend(their_version);
+        } else if start == end {
+            // both sides only added lines here
+            merged.extend(&our_version);
+            merged.extend(their_version.iter().filter(|l| !our_version.contains(l)));
+        } else {
+            return None;
+        }
+        pos = end;
+    }
+    merged.extend(&base_lines[pos..]);
+
+    let mut content = merged.join("\n");
+    if ours.ends_with('\n') || theirs.ends_with('\n') {
+        content.push('\n');
+    }
+    Some(content)
+}
+
+/// Both versions of a file with the lines that differ between them wrapped in
+/// `<<<<<<<`, `=======` and `>>>>>>>` markers, to be fixed up by hand.
+pub fn conflict_markers(ours: &str, theirs: &str, our_label: &str, their_label: &str) -> String {
+    let our_lines = ours.lines().collect::<Vec<_>>();
+    let their_lines = theirs.lines().collect::<Vec<_>>();
+
+    let mut content = String::new();
+    let mut push = |line: &str| {
+        content.push_str(line);
+        content.push('\n');
+    };
+    let mut pos = 0;
+    for hunk in hunks(&our_lines, &their_lines) {
+        our_lines[pos..hunk.start].iter().for_each(|l| push(l));
+        push(&format!("<<<<<<< {}", our_label));
+        our_lines[hunk.start..hunk.end].iter().for_each(|l| push(l));
+        push("=======");
+        hunk.lines.iter().for_each(|l| push(l));
+        push(&format!(">>>>>>> {}", their_label));
+        pos = hunk.end;
+    }
+    our_lines[pos..].iter().for_each(|l| push(l));
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_changes_to_different_lines() {
+        let base = "# day\n- [ ] a\n- [ ] b\n- [ ] c\n";
+        let ours = "# day\n- [x] a\n- [ ] b\n- [ ] c\n";
+        let theirs = "# day\n- [ ] a\n- [ ] b\n- [x] c\n";
+        assert_eq!(
+            merge(base, ours, theirs).unwrap(),
+            "# day\n- [x] a\n- [ ] b\n- [x] c\n"
+        );
+    }
+
+    #[test]
+    fn same_change_on_both_sides() {
+        let base = "a\nb\n";
+        let both = "a\nB\n";
+        assert_eq!(merge(base, both, both).unwrap(), both);
+        assert_eq!(merge(base, base, base).unwrap(), base);
+    }
+
+    #[test]
+    fn keeps_lines_both_sides_added_at_the_same_point() {
+        let base = "a\nb\n";
+        let ours = "a\nours\nb\n";
+        let theirs = "a\ntheirs\nb\n";
+        assert_eq!(merge(base, ours, theirs).unwrap(), "a\nours\ntheirs\nb\n");
+
+        // at the end of the file
+        let ours = "a\nb\nours\n";
+        let theirs = "a\nb\ntheirs\n";
+        assert_eq!(merge(base, ours, theirs).unwrap(), "a\nb\nours\ntheirs\n");
+    }
+
+    #[test]
+    fn adds_lines_both_sides_added_only_once() {
+        let base = "a\n";
+        let ours = "a\nshared\nours\n";
+        let theirs = "a\nshared\ntheirs\n";
+        assert_eq!(
+            merge(base, ours, theirs).unwrap(),
+            "a\nshared\nours\ntheirs\n"
+        );
+    }
+
+    #[test]
+    fn delete_next_to_an_edit() {
+        let base = "a\nb\nc\n";
+        assert_eq!(merge(base, "a\nc\n", "a\nb\nC\n").unwrap(), "a\nC\n");
+        assert_eq!(merge(base, "A\nb\nc\n", "a\nc\n").unwrap(), "A\nc\n");
+    }
+
+    #[test]
+    fn edit_then_insert_at_the_end() {
+        let base = "a\nb\n";
+        assert_eq!(merge(base, "a\nB\n", "a\nb\nc\n").unwrap(), "a\nB\nc\n");
+        assert_eq!(merge(base, "a\nb\nc\n", "a\nB\n").unwrap(), "a\nB\nc\n");
+    }
+
+    #[test]
+    fn conflicting_changes() {
+        let base = "a\nb\nc\n";
+        assert_eq!(merge(base, "a\nours\nc\n", "a\ntheirs\nc\n"), None);
+        // one side deletes a line the other changed
+        assert_eq!(merge(base, "a\nc\n", "a\nB\nc\n"), None);
+    }
+
+    #[test]
+    fn keeps_a_trailing_newline_from_either_side() {
+        assert_eq!(merge("a\n", "a", "a\n").unwrap(), "a\n");
+        assert_eq!(merge("a", "a\n", "a").unwrap(), "a\n");
+        assert_eq!(merge("a\n", "a", "a").unwrap(), "a");
+        assert_eq!(merge("a\n", "a\nb", "c\na").unwrap(), "c\na\nb");
+    }
+
+    #[test]
+    fn merges_with_an_empty_base() {
+        assert_eq!(merge("", "a\n", "a\n").unwrap(), "a\n");
+        assert_eq!(
+            merge("", "# day\n- [ ] a\n", "# day\n- [ ] b\n").unwrap(),
+            "# day\n- [ ] a\n- [ ] b\n"
+        );
+    }
+
+    #[test]
+    fn marks_the_lines_that_differ() {
+        assert_eq!(
+            conflict_markers("a\nours\nc\n", "a\ntheirs\nc\n", "mine", "synced"),
+            "a\n<<<<<<< mine\nours\n=======\ntheirs\n>>>>>>> synced\nc\n"
+        );
+        assert_eq!(
+            conflict_markers("a\n", "a\nadded\n", "mine", "synced"),
+            "a\n<<<<<<< mine\n=======\nadded\n>>>>>>> synced\n"
+        );
+        assert_eq!(
+            conflict_markers("a\nb\n", "a\nb\n", "mine", "synced"),
+            "a\nb\n"
+        );
+    }
+}
@@ -1,3 +1,6 @@
 pub mod auth;
+pub mod conflicts;
 mod constants;
 pub mod files;
+mod merge;
+mod state;
@@ -0,0 +1,72 @@
+use crate::util::get_doto_path;
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// What every file looked like at the last sync, kept in `.doto-sync.json`.
+/// Changes on either side are found by comparing content hashes against it,
+/// so clocks on different machines don't matter.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SyncState {
+    pub files: BTreeMap<String, SyncedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncedFile {
+    /// sha256 of the content both sides had
+    pub hash: String,
+    /// the server's `last_modified` for that content, unknown right after an
+    /// upload until the next sync lists it
+    pub revision: Option<String>,
+}
+
+fn state_path() -> PathBuf {
+    PathBuf::from(get_doto_path()).join(".doto-sync.json")
+}
+
+impl SyncState {
+    pub fn load() -> SyncState {
+        match std::fs::read_to_string(state_path()) {
+            Ok(content) => serde_json::from_str(&content).expect("Failed to parse sync state"),
+            Err(_) => SyncState::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let content = serde_json::to_string_pretty(self).expect("Failed to serialize sync state");
+        std::fs::write(state_path(), content).expect("Failed to write sync state");
+    }
+
+    /// Records `file` as it is now as synced, keeping a copy to merge against.
+    pub fn synced(&mut self, file: &Path, hash: String, revision: Option<String>) {
+        let base_path = base_path(file);
+        std::fs::create_dir_all(base_path.parent().unwrap())
+            .expect("Failed to create sync directory");
+        std::fs::copy(file, base_path).expect("Failed to save last synced version");
+        self.files
+            .insert(file_name(file), SyncedFile { hash, revision });
+    }
+
+    pub fn forget(&mut self, file: &Path) {
+        let _ = std::fs::remove_file(base_path(file));
+        self.files.remove(&file_name(file));
+    }
+}
+
+fn file_name(file: &Path) -> String {
+    file.file_name().unwrap().to_str().unwrap().to_string()
+}
+
+/// Where the version of a file from the last sync is kept, to merge against.
+fn base_path(file: &Path) -> PathBuf {
+    PathBuf::from(get_doto_path())
+        .join(".doto-sync")
+        .join(file.file_name().unwrap())
+}
+
+pub fn read_base(file: &Path) -> Option<String> {
+    std::fs::read_to_string(base_path(file)).ok()
+}
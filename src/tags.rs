@@ -0,0 +1,291 @@
+use crate::{
+    history::load_days,
+    task::{DayFile, Line, Status, Task},
+    util::get_doto_path,
+};
+
+use similar::{capture_diff_slices, Algorithm, DiffOp};
+use std::{collections::BTreeMap, path::PathBuf};
+
+const FILTER_PREFIX: &str = "<!-- only tasks tagged ";
+const FILTER_SUFFIX: &str = " -->";
+
+/// Whether a task has every tag in `tags`. A tag written without `+` or `@`
+/// matches either kind.
+pub fn has_tags(task: &Task, tags: &[String]) -> bool {
+    tags.iter().all(|wanted| {
+        task.tags().any(|tag| match wanted.starts_with(['+', '@']) {
+            true => tag.eq_ignore_ascii_case(wanted),
+            false => tag[1..].eq_ignore_ascii_case(wanted),
+        })
+    })
+}
+
+#[derive(Debug, Default)]
+struct TagCount {
+    /// the tag as it was first written, tags are counted regardless of case
+    name: String,
+    open: usize,
+    completed: usize,
+}
+
+/// Prints every tag in the day files and later.md with its open and completed
+/// task counts. Rescheduled tasks are left out since their copy is counted.
+pub fn show_tags() {
+    let mut files = load_days()
+        .into_iter()
+        .map(|(_, file)| file)
+        .collect::<Vec<_>>();
+    let later_path = PathBuf::from(format!("{}/later.md", get_doto_path()));
+    if let Ok(later) = DayFile::load(&later_path) {
+        files.push(later);
+    }
+
+    let mut counts: BTreeMap<String, TagCount> = BTreeMap::new();
+    for task in files.iter().flat_map(|file| file.tasks()) {
+        for tag in task.tags() {
+            let count = counts.entry(tag.to_lowercase()).or_default();
+            if count.name.is_empty() {
+                count.name = tag.to_string();
+            }
+            match task.status {
+                Status::Open => count.open += 1,
+                Status::Completed => count.completed += 1,
+                Status::Rescheduled => {}
+            }
+        }
+    }
+
+    if counts.is_empty() {
+        println!("No tags found");
+        return;
+    }
+    let width = counts.values().map(|c| c.name.len()).max().unwrap_or(0);
+    for count in counts.values() {
+        println!(
+            "{:<width$}  {:>3} open  {:>3} done",
+            count.name,
+            count.open,
+            count.completed,
+            width = width
+        );
+    }
+}
+
+/// First line of a combined view filtered to `tags`, so the hidden lines can
+/// be put back when it's saved, even by a later session recovering it.
+pub fn filter_line(tags: &[String]) -> String {
+    format!("{}{}{}", FILTER_PREFIX, tags.join(" "), FILTER_SUFFIX)
+}
+
+pub fn parse_filter_line(line: &str) -> Option<Vec<String>> {
+    let tags = line
+        .strip_prefix(FILTER_PREFIX)?
+        .strip_suffix(FILTER_SUFFIX)?;
+    Some(tags.split_whitespace().map(|t| t.to_string()).collect())
+}
+
+/// Which lines of a day file are shown in a view filtered to `tags`: headings,
+/// tasks with the tags and anything indented under them.
+pub fn visible_lines(file: &DayFile, tags: &[String]) -> Vec<bool> {
+    if tags.is_empty() {
+        return vec![true; file.lines.len()];
+    }
+    // depth of the tagged task the following lines might be nested under
+    let mut parent: Option<usize> = None;
+    file.lines
+        .iter()
+        .map(|line| match line {
+            Line::Heading(_) => {
+                parent = None;
+                true
+            }
+            Line::Task(task) => {
+                if parent.is_some_and(|depth| task.depth() > depth) {
+                    return true;
+                }
+                parent = has_tags(task, tags).then(|| task.depth());
+                parent.is_some()
+            }
+            Line::Text(text) => {
+                parent.is_some() && text.starts_with(char::is_whitespace) && !text.trim().is_empty()
+            }
+        })
+        .collect()
+}
+
+/// Puts the lines a filtered view hid back into the edited content of a file.
+/// Each hidden line stays just before the shown line it preceded, using a
+/// diff between the lines that were shown and the edited ones.
+pub fn merge_hidden(existing: &str, edited: &str, tags: &[String]) -> String {
+    let visible = visible_lines(&DayFile::parse(existing), tags);
+    let mut shown = vec![];
+    // hidden_before[i] are the hidden lines between shown[i - 1] and shown[i]
+    let mut hidden_before: Vec<Vec<&str>> = vec![vec![]];
+    for (line, visible) in existing.lines().zip(visible) {
+        if visible {
+            shown.push(line);
+            hidden_before.push(vec![]);
+        } else {
+            hidden_before.last_mut().unwrap().push(line);
+        }
+    }
+    let edited = edited.lines().collect::<Vec<_>>();
+
+    let mut lines: Vec<&str> = vec![];
+    for op in capture_diff_slices(Algorithm::Myers, &shown, &edited) {
+        let (old_index, old_len, new_index, new_len) = match op {
+            DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => (old_index, len, new_index, len),
+            DiffOp::Delete {
+                old_index,
+                old_len,
+                new_index,
+            } => (old_index, old_len, new_index, 0),
+            DiffOp::Insert {
+                old_index,
+                new_index,
+                new_len,
+            } => (old_index, 0, new_index, new_len),
+            DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => (old_index, old_len, new_index, new_len),
+        };
+        for i in 0..old_len.max(new_len) {
+            if i < old_len {
+                lines.extend(&hidden_before[old_index + i]);
+            }
+            if i < new_len {
+                lines.push(edited[new_index + i]);
+            }
+        }
+    }
+    lines.extend(hidden_before.last().unwrap());
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "# 2023-05-24
+- [ ] plain
+- [ ] tagged +work
+  - [ ] subtask
+  note
+- [ ] other
+## section
+- [ ] also +work
+- [ ] last
+";
+
+    fn tags() -> Vec<String> {
+        vec!["+work".to_string()]
+    }
+
+    /// What the filtered view shows of `FILE`.
+    fn shown() -> String {
+        let visible = visible_lines(&DayFile::parse(FILE), &tags());
+        FILE.lines()
+            .zip(visible)
+            .filter(|(_, v)| *v)
+            .map(|(l, _)| format!("{}\n", l))
+            .collect()
+    }
+
+    #[test]
+    fn shows_headings_and_tagged_tasks_with_what_is_under_them() {
+        assert_eq!(
+            shown(),
+            "# 2023-05-24\n- [ ] tagged +work\n  - [ ] subtask\n  note\n## section\n- [ ] also +work\n"
+        );
+        let all = visible_lines(&DayFile::parse(FILE), &[]);
+        assert!(all.iter().all(|v| *v));
+    }
+
+    #[test]
+    fn matches_tags_regardless_of_case_and_kind() {
+        let task = Task::parse("- [ ] call @Phone about +pgMagic.").unwrap();
+        assert!(has_tags(&task, &["+pgmagic".to_string()]));
+        assert!(has_tags(
+            &task,
+            &["phone".to_string(), "pgMagic".to_string()]
+        ));
+        assert!(!has_tags(&task, &["@pgMagic".to_string()]));
+        assert!(!has_tags(&task, &["+work".to_string()]));
+    }
+
+    #[test]
+    fn unchanged_view_keeps_the_file() {
+        assert_eq!(merge_hidden(FILE, &shown(), &tags()), FILE);
+    }
+
+    #[test]
+    fn edits_next_to_hidden_lines() {
+        let edited = shown()
+            .replace("- [ ] tagged +work", "- [x] tagged +work")
+            .replace("- [ ] also +work", "- [x] also +work");
+        assert_eq!(
+            merge_hidden(FILE, &edited, &tags()),
+            FILE.replace("- [ ] tagged +work", "- [x] tagged +work")
+                .replace("- [ ] also +work", "- [x] also +work")
+        );
+    }
+
+    #[test]
+    fn deleting_a_shown_line_keeps_the_hidden_ones_around_it() {
+        let edited = shown().replace("- [ ] also +work\n", "");
+        assert_eq!(
+            merge_hidden(FILE, &edited, &tags()),
+            FILE.replace("- [ ] also +work\n", "")
+        );
+    }
+
+    #[test]
+    fn inserted_lines_go_right_after_the_line_before_them() {
+        let edited = shown().replace("  note\n", "  note\n- [ ] new +work\n");
+        assert_eq!(
+            merge_hidden(FILE, &edited, &tags()),
+            FILE.replace("  note\n", "  note\n- [ ] new +work\n")
+        );
+
+        let edited = format!("{}- [ ] at the end +work\n", shown());
+        assert_eq!(
+            merge_hidden(FILE, &edited, &tags()),
+            FILE.replace(
+                "- [ ] also +work\n",
+                "- [ ] also +work\n- [ ] at the end +work\n"
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_hidden_lines_at_the_end() {
+        let edited = shown().replace("- [ ] also +work", "- [ ] also +work edited");
+        let merged = merge_hidden(FILE, &edited, &tags());
+        assert!(merged.ends_with("- [ ] also +work edited\n- [ ] last\n"));
+
+        // even when everything shown was deleted
+        assert_eq!(
+            merge_hidden(FILE, "", &tags()),
+            "- [ ] plain\n- [ ] other\n- [ ] last\n"
+        );
+    }
+
+    #[test]
+    fn round_trips_the_filter_line() {
+        let line = filter_line(&tags());
+        assert_eq!(line, "<!-- only tasks tagged +work -->");
+        assert_eq!(parse_filter_line(&line), Some(tags()));
+        assert_eq!(parse_filter_line("# 2023-05-24"), None);
+    }
+}
@@ -0,0 +1,525 @@
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{fmt, path::Path};
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Open,
+    Completed,
+    Rescheduled,
+}
+
+impl Status {
+    fn from_mark(mark: &str) -> Option<Status> {
+        match mark {
+            "" | " " => Some(Status::Open),
+            "x" | "X" => Some(Status::Completed),
+            ">" => Some(Status::Rescheduled),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Open => "open",
+            Status::Completed => "completed",
+            Status::Rescheduled => "rescheduled",
+        }
+    }
+
+    pub fn mark(&self) -> &'static str {
+        match self {
+            Status::Open => " ",
+            Status::Completed => "x",
+            Status::Rescheduled => ">",
+        }
+    }
+}
+
+/// A single `- [ ] task` line.
+///
+/// Whitespace around each part is kept so that an unmodified task is written
+/// back exactly as it was read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Task {
+    pub indent: String,
+    pub status: Status,
+    /// the date a `- [>] (date) task` was moved to
+    pub moved_to: Option<NaiveDate>,
+    pub text: String,
+    /// the day file a task was carried over from, written as a trailing `(date)`
+    pub origin: Option<NaiveDate>,
+    /// the `##` heading the task lives under, if any
+    pub section: Option<String>,
+    mark: String,
+    gap: String,
+    moved_to_gap: String,
+    origin_gap: String,
+    trailing: String,
+}
+
+impl Task {
+    pub fn new(text: &str) -> Task {
+        Task {
+            indent: String::new(),
+            status: Status::Open,
+            moved_to: None,
+            text: text.to_string(),
+            origin: None,
+            section: None,
+            mark: " ".to_string(),
+            gap: " ".to_string(),
+            moved_to_gap: " ".to_string(),
+            origin_gap: " ".to_string(),
+            trailing: String::new(),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Task> {
+        let rest = line.trim_start();
+        let indent = &line[..line.len() - rest.len()];
+        let rest = rest.strip_prefix("- [")?;
+        let close = rest.find(']')?;
+        let mark = &rest[..close];
+        let status = Status::from_mark(mark)?;
+        let body = &rest[close + 1..];
+
+        let content = body.trim_start();
+        let gap = &body[..body.len() - content.len()];
+
+        let mut moved_to = None;
+        let mut moved_to_gap = " ";
+        let mut content = content;
+        // only moved tasks start with the date they were moved to, an open
+        // task's text may well start with a date of its own
+        let date = (status == Status::Rescheduled)
+            .then(|| content.get(..12).and_then(parse_bracketed_date))
+            .flatten();
+        if let Some(date) = date {
+            let after = &content[12..];
+            let text = after.trim_start();
+            moved_to = Some(date);
+            moved_to_gap = &after[..after.len() - text.len()];
+            content = text;
+        }
+
+        let core = content.trim_end();
+        let trailing = &content[core.len()..];
+
+        let mut origin = None;
+        let mut origin_gap = " ";
+        let mut text = core;
+        if core.len() >= 12 {
+            if let Some(date) = core.get(core.len() - 12..).and_then(parse_bracketed_date) {
+                let before = &core[..core.len() - 12];
+                let trimmed = before.trim_end();
+                if before.is_empty() || trimmed.len() < before.len() {
+                    origin = Some(date);
+                    origin_gap = &before[trimmed.len()..];
+                    text = trimmed;
+                }
+            }
+        }
+
+        Some(Task {
+            indent: indent.to_string(),
+            status,
+            moved_to,
+            text: text.to_string(),
+            origin,
+            section: None,
+            mark: mark.to_string(),
+            gap: gap.to_string(),
+            moved_to_gap: moved_to_gap.to_string(),
+            origin_gap: origin_gap.to_string(),
+            trailing: trailing.to_string(),
+        })
+    }
+
+    /// Width of the leading whitespace, counting a tab as four columns.
+    pub fn depth(&self) -> usize {
+        self.indent
+            .chars()
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum()
+    }
+
+    /// The date of a todo.txt style `due:2023-06-01` token in the text.
+    pub fn due(&self) -> Option<NaiveDate> {
+        self.text
+            .split_whitespace()
+            .find_map(|word| word.strip_prefix("due:"))
+            .and_then(|date| NaiveDate::parse_from_str(date, DATE_FORMAT).ok())
+    }
+
+    /// A todo.txt style `(A)` at the start of the text, or a `!!!`, `!!` or `!`
+    /// word anywhere in it, which count as `A`, `B` and `C`.
+    pub fn priority(&self) -> Option<char> {
+        let bytes = self.text.as_bytes();
+        if let [b'(', p @ b'A'..=b'Z', b')', rest @ ..] = bytes {
+            if rest.first().is_none_or(|c| c.is_ascii_whitespace()) {
+                return Some(*p as char);
+            }
+        }
+        self.text.split_whitespace().find_map(|word| match word {
+            "!!!" => Some('A'),
+            "!!" => Some('B'),
+            "!" => Some('C'),
+            _ => None,
+        })
+    }
+
+    /// todo.txt style `+project` and `@context` tokens in the text.
+    pub fn tags(&self) -> impl Iterator<Item = &str> {
+        self.text.split_whitespace().filter_map(|word| {
+            let word = word.trim_end_matches(|c: char| c.is_ascii_punctuation() && c != '_');
+            let name = word.strip_prefix(['+', '@'])?;
+            name.starts_with(char::is_alphabetic).then_some(word)
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.status == Status::Open
+    }
+
+    /// Marks the task as `- [>] (date)`, keeping the spacing before the text.
+    pub fn reschedule(&mut self, date: NaiveDate) {
+        if self.moved_to.is_none() && (!self.gap.is_empty() || self.text.is_empty()) {
+            self.moved_to_gap = std::mem::replace(&mut self.gap, " ".to_string());
+        }
+        self.status = Status::Rescheduled;
+        self.moved_to = Some(date);
+    }
+
+    /// Marks a completed or rescheduled task as open again.
+    pub fn reopen(&mut self) {
+        if self.moved_to.take().is_some() {
+            self.gap = std::mem::replace(&mut self.moved_to_gap, " ".to_string());
+        }
+        self.status = Status::Open;
+    }
+}
+
+impl fmt::Display for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mark = match Status::from_mark(&self.mark) {
+            Some(status) if status == self.status => self.mark.as_str(),
+            _ => self.status.mark(),
+        };
+        let has_content = self.moved_to.is_some() || !self.text.is_empty();
+        let gap = if self.gap.is_empty() && has_content && mark != self.mark {
+            " "
+        } else {
+            self.gap.as_str()
+        };
+        write!(f, "{}- [{}]{}", self.indent, mark, gap)?;
+        if let Some(date) = self.moved_to {
+            write!(f, "({}){}", date.format(DATE_FORMAT), self.moved_to_gap)?;
+        }
+        write!(f, "{}", self.text)?;
+        if let Some(date) = self.origin {
+            write!(f, "{}({})", self.origin_gap, date.format(DATE_FORMAT))?;
+        }
+        write!(f, "{}", self.trailing)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heading {
+    pub level: usize,
+    pub title: String,
+    gap: String,
+}
+
+impl Heading {
+    pub fn new(level: usize, title: &str) -> Heading {
+        Heading {
+            level,
+            title: title.to_string(),
+            gap: " ".to_string(),
+        }
+    }
+
+    pub fn parse(line: &str) -> Option<Heading> {
+        let rest = line.trim_start_matches('#');
+        let level = line.len() - rest.len();
+        if level == 0 || !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+            return None;
+        }
+        let title = rest.trim_start();
+        Some(Heading {
+            level,
+            title: title.to_string(),
+            gap: rest[..rest.len() - title.len()].to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Heading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", "#".repeat(self.level), self.gap, self.title)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+    Heading(Heading),
+    Task(Task),
+    Text(String),
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Heading(heading) => write!(f, "{}", heading),
+            Line::Task(task) => write!(f, "{}", task),
+            Line::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// A parsed todo file. Writing it back with `to_string` reproduces the
+/// original file byte for byte unless its lines were changed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DayFile {
+    pub lines: Vec<Line>,
+    trailing_newline: bool,
+    /// whether lines end in `\r\n`, going by the first one
+    crlf: bool,
+}
+
+impl DayFile {
+    pub fn parse(content: &str) -> DayFile {
+        let mut section: Option<String> = None;
+        let lines = content
+            .lines()
+            .map(|line| {
+                if let Some(heading) = Heading::parse(line) {
+                    if heading.level > 1 {
+                        section = Some(heading.title.trim().to_string());
+                    } else {
+                        section = None;
+                    }
+                    Line::Heading(heading)
+                } else if let Some(mut task) = Task::parse(line) {
+                    task.section = section.clone();
+                    Line::Task(task)
+                } else {
+                    Line::Text(line.to_string())
+                }
+            })
+            .collect();
+
+        DayFile {
+            lines,
+            trailing_newline: content.ends_with('\n'),
+            crlf: content
+                .find('\n')
+                .is_some_and(|i| content[..i].ends_with('\r')),
+        }
+    }
+
+    pub fn load(path: &Path) -> std::io::Result<DayFile> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(DayFile::parse(&content))
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+
+    pub fn tasks(&self) -> impl Iterator<Item = &Task> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Task(task) => Some(task),
+            _ => None,
+        })
+    }
+
+    /// Inserts tasks after the last line of a `##` section, adding the heading
+    /// at the end of the file if it doesn't exist yet. Tasks without a section
+    /// go before the first `##` heading. Returns the index of the first task.
+    pub fn insert_in_section(&mut self, section: Option<&Heading>, tasks: Vec<Task>) -> usize {
+        let index = match section {
+            Some(heading) => self.section_end(heading),
+            None => {
+                let end = self
+                    .lines
+                    .iter()
+                    .position(|l| matches!(l, Line::Heading(h) if h.level > 1))
+                    .unwrap_or(self.lines.len());
+                self.last_content_line(0, end)
+            }
+        };
+        let title = section.map(|h| h.title.trim().to_string());
+        let lines = tasks.into_iter().map(|mut task| {
+            task.section = title.clone();
+            Line::Task(task)
+        });
+        self.lines.splice(index..index, lines);
+        index
+    }
+
+    fn section_end(&mut self, section: &Heading) -> usize {
+        let start = self.lines.iter().position(
+            |l| matches!(l, Line::Heading(h) if h.level > 1 && h.title.trim() == section.title.trim()),
+        );
+        let Some(start) = start else {
+            if matches!(self.lines.last(), Some(l) if !l.to_string().trim().is_empty()) {
+                self.lines.push(Line::Text(String::new()));
+            }
+            self.lines.push(Line::Heading(section.clone()));
+            return self.lines.len();
+        };
+        let level = match &self.lines[start] {
+            Line::Heading(h) => h.level,
+            _ => unreachable!(),
+        };
+        let end = self.lines[start + 1..]
+            .iter()
+            .position(|l| matches!(l, Line::Heading(h) if h.level <= level))
+            .map_or(self.lines.len(), |i| start + 1 + i);
+        self.last_content_line(start + 1, end)
+    }
+
+    /// Index just after the last non-blank line in `start..end`.
+    fn last_content_line(&self, start: usize, end: usize) -> usize {
+        self.lines[start..end]
+            .iter()
+            .rposition(|l| !l.to_string().trim().is_empty())
+            .map_or(start, |i| start + i + 1)
+    }
+}
+
+impl fmt::Display for DayFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                f.write_str(newline)?;
+            }
+            write!(f, "{}", line)?;
+        }
+        if self.trailing_newline {
+            f.write_str(newline)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the date out of a `YYYY-MM-DD.md` day file name.
+pub fn date_from_path(path: &Path) -> Option<NaiveDate> {
+    let stem = path.file_stem()?.to_str()?;
+    NaiveDate::parse_from_str(stem, DATE_FORMAT).ok()
+}
+
+/// Parses a `(YYYY-MM-DD)` marker.
+pub fn parse_bracketed_date(s: &str) -> Option<NaiveDate> {
+    let date = s.strip_prefix('(')?.strip_suffix(')')?;
+    if date.len() != 10 {
+        return None;
+    }
+    NaiveDate::parse_from_str(date, DATE_FORMAT).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, DATE_FORMAT).unwrap()
+    }
+
+    fn round_trip(content: &str) {
+        assert_eq!(DayFile::parse(content).to_string(), content);
+    }
+
+    #[test]
+    fn round_trips_odd_task_lines() {
+        round_trip("# 2023-05-24\n- []\n- [ ]\n- [x]text\n- [X]  two spaces  \n");
+        round_trip("- [>]   (2023-05-25)\t  moved\n- [>](2023-05-25)text\n");
+        round_trip("- [ ] (2026-11-02) dentist appointment\n- [x](2026-11-02)  done\n");
+        round_trip("- [ ] carried over (2023-05-20)\n- [ ] gap\t\t(2023-05-20)  \n");
+        round_trip("\t- [ ] tab indented\n    - [x] spaces\n\tnote\n");
+        round_trip("## section\n- [ ] no trailing newline");
+        round_trip("");
+        round_trip("\n\n");
+    }
+
+    #[test]
+    fn round_trips_crlf() {
+        round_trip("# 2023-05-24\r\n- [ ] a\r\n- [x] b\r\n");
+        round_trip("- [ ] a\r\n- [ ] b");
+
+        let mut file = DayFile::parse("- [ ] a\r\n- [ ] b\r\n");
+        if let Line::Task(task) = &mut file.lines[0] {
+            task.status = Status::Completed;
+        }
+        assert_eq!(file.to_string(), "- [x] a\r\n- [ ] b\r\n");
+    }
+
+    #[test]
+    fn parses_parts() {
+        let task = Task::parse("  - [>]  (2023-05-25) write tests (2023-05-20) ").unwrap();
+        assert_eq!(task.indent, "  ");
+        assert_eq!(task.status, Status::Rescheduled);
+        assert_eq!(task.moved_to, Some(date("2023-05-25")));
+        assert_eq!(task.text, "write tests");
+        assert_eq!(task.origin, Some(date("2023-05-20")));
+
+        let task = Task::parse("- [x]text").unwrap();
+        assert_eq!(task.status, Status::Completed);
+        assert_eq!(task.text, "text");
+
+        let task = Task::parse("- [ ] meet(2023-05-20)").unwrap();
+        assert_eq!(task.text, "meet(2023-05-20)");
+        assert_eq!(task.origin, None);
+
+        let task = Task::parse("- [ ] (2026-11-02) dentist appointment").unwrap();
+        assert_eq!(task.moved_to, None);
+        assert_eq!(task.text, "(2026-11-02) dentist appointment");
+
+        assert!(Task::parse("- [?] unknown").is_none());
+        assert!(Task::parse("- plain item").is_none());
+    }
+
+    #[test]
+    fn reschedule_and_reopen() {
+        let mut task = Task::parse("- [ ]   spaced out (2023-05-20)").unwrap();
+        task.reschedule(date("2023-05-25"));
+        assert_eq!(
+            task.to_string(),
+            "- [>] (2023-05-25)   spaced out (2023-05-20)"
+        );
+        task.reopen();
+        assert_eq!(task.to_string(), "- [ ]   spaced out (2023-05-20)");
+
+        let mut task = Task::parse("- []").unwrap();
+        task.reschedule(date("2023-05-25"));
+        assert_eq!(task.to_string(), "- [>] (2023-05-25)");
+        task.reopen();
+        assert_eq!(task.to_string(), "- []");
+
+        let mut task = Task::parse("- [x]done").unwrap();
+        task.reopen();
+        assert_eq!(task.to_string(), "- [ ] done");
+
+        let mut task = Task::parse("- [ ] (2026-11-02) dentist appointment").unwrap();
+        task.reschedule(date("2026-10-18"));
+        assert_eq!(
+            task.to_string(),
+            "- [>] (2026-10-18) (2026-11-02) dentist appointment"
+        );
+        task.reopen();
+        assert_eq!(task.to_string(), "- [ ] (2026-11-02) dentist appointment");
+
+        let mut task = Task::parse("\t- [>] (2023-05-21)\tmoved").unwrap();
+        task.reopen();
+        assert_eq!(task.to_string(), "\t- [ ]\tmoved");
+        task.reschedule(date("2023-05-25"));
+        assert_eq!(task.to_string(), "\t- [>] (2023-05-25)\tmoved");
+    }
+}
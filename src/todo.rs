@@ -1,11 +1,22 @@
-use crate::util::{get_doto_path, get_today_todo_file_path};
+use crate::{
+    config::ViewMode,
+    due::{annotate_source, apply_overdue, overdue_tasks, OVERDUE},
+    editor::open_in_editor,
+    history::{annotate_age, chain_key, strip_age, task_starts},
+    recurring::add_recurring_tasks,
+    sync::conflicts::is_conflict_file,
+    tags::{filter_line, has_tags, merge_hidden, parse_filter_line, visible_lines},
+    task::{date_from_path, DayFile, Heading, Line, Task},
+    util::{ask, confirm, get_doto_path, get_today_filename},
+};
 
-use chrono::{Datelike, Duration, NaiveDate};
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use similar::TextDiff;
 use std::{
+    collections::HashSet,
     fs::File,
-    io::{BufRead, BufReader, Write},
-    path::PathBuf,
-    process::Command,
+    io::Write,
+    path::{Path, PathBuf},
 };
 
 pub fn open_date(date: String) {
@@ -22,90 +33,380 @@ pub fn open_date(date: String) {
     open_file(date);
 }
 
-pub fn open_week() {
+/// First and last day (inclusive) shown in the combined view.
+pub fn view_range(mode: ViewMode, back: i64, forward: i64) -> (NaiveDate, NaiveDate) {
+    let today = chrono::Local::now().date_naive();
+    match mode {
+        ViewMode::Days => (
+            today - Duration::days(back),
+            today + Duration::days(forward),
+        ),
+        ViewMode::Week => {
+            let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+            (monday, monday + Duration::days(6))
+        }
+        ViewMode::Month => {
+            let first = today.with_day(1).unwrap();
+            let next_month = first.checked_add_months(Months::new(1)).unwrap();
+            (first, next_month.pred_opt().unwrap())
+        }
+    }
+}
+
+/// Adds an open task to a day file (today by default) without opening the editor.
+pub fn add_task(text: String, date: Option<String>, section: Option<String>, edit: bool) {
+    let filename = resolve_filename(date);
+    let path = PathBuf::from(get_or_make_file(filename.clone()));
+
+    let mut day_file = DayFile::load(&path).expect("Could not read todo file");
+    let section = section.map(|title| Heading::new(2, &title));
+    let index = day_file.insert_in_section(section.as_ref(), vec![Task::new(&text)]);
+    day_file.save(&path).expect("Could not write todo file");
+
+    println!("Added \"{}\" to {}", text, filename);
+    if edit {
+        open_file_at(filename, Some(index + 1));
+    }
+}
+
+/// File name (without ".md") for a date argument, defaulting to today.
+pub fn resolve_filename(date: Option<String>) -> String {
+    match date {
+        Some(date) if date == "later" => date,
+        Some(date) => parse_day_string(date)
+            .expect("Invalid date")
+            .format("%Y-%m-%d")
+            .to_string(),
+        None => get_today_filename(),
+    }
+}
+
+/// Opens the days from `start_of_range` to `end_of_range` in one file. With
+/// `tags`, only headings and tasks with those tags are shown.
+pub fn open_week(start_of_range: NaiveDate, end_of_range: NaiveDate, later: bool, tags: &[String]) {
     let doto_path = get_doto_path();
-    let combined_path = format!("{}/todo.md", doto_path);
-    let today = chrono::Local::now().naive_local();
-    // let start_of_week = today - Duration::days(today.weekday().num_days_from_monday() as i64);
-    let start = today;
-    let start_of_range = start - Duration::days(4);
-    let end_of_range = start + Duration::days(3);
+    // each session gets its own scratch file, hidden from get_all_files
+    let combined_name = format!(".todo-{}", std::process::id());
+    let combined_path = format!("{}/{}.md", doto_path, combined_name);
+    let today = chrono::Local::now().date_naive();
+
+    let task_starts = task_starts();
 
     let mut combined_file =
         File::create(combined_path.clone()).expect("Failed to create combined file");
+    if !tags.is_empty() {
+        writeln!(combined_file, "{}", filter_line(tags)).expect("failed to write line");
+    }
+
+    // past-due tasks that wouldn't otherwise be in the view go first
+    let overdue = overdue_tasks(start_of_range, end_of_range, later)
+        .into_iter()
+        .filter(|(_, _, task)| has_tags(task, tags))
+        .collect::<Vec<_>>();
+    if !overdue.is_empty() {
+        writeln!(combined_file, "# {}", OVERDUE).expect("failed to write line");
+        for (name, line, task) in &overdue {
+            writeln!(combined_file, "{}", annotate_source(task, name, *line))
+                .expect("failed to write line");
+        }
+        combined_file
+            .write_all(b"---\n")
+            .expect("failed to write line");
+    }
+
     let mut day = start_of_range;
-    while day < end_of_range {
+    while day <= end_of_range {
         let date = day.format("%Y-%m-%d").to_string();
         // TODO: make function that will create the file
         let path = PathBuf::from(get_or_make_file(date.clone()));
         if path.exists() {
-            let file = File::open(path).expect("Failed to open file");
-            let reader = BufReader::new(file);
-            reader.lines().for_each(|line| {
-                let line = line.expect("Failed to read line");
+            let content = std::fs::read_to_string(path).expect("Failed to read file");
+            let visible = visible_lines(&DayFile::parse(&content), tags);
+            for (line, _) in content.lines().zip(visible).filter(|(_, v)| *v) {
+                let mut line = line.to_string();
+                // show how long an open task has been carried over for
+                if let Some(task) = Task::parse(&line).filter(|t| t.is_open()) {
+                    let key = (day, chain_key(&task.text));
+                    if let Some(start) = task_starts.get(&key) {
+                        let age = (today - *start).num_days();
+                        if age > 0 {
+                            line = annotate_age(&line, age);
+                        }
+                    }
+                }
                 combined_file
                     .write_fmt(format_args!("{}\n", line))
                     .expect("failed to write line");
-            });
+            }
             combined_file
                 .write_all(b"---\n")
                 .expect("failed to write line");
         }
-        day = day + Duration::days(1);
+        day += Duration::days(1);
     }
 
     // append the later file
-    let later_path = get_or_make_file("later".to_string());
-    let later_file = File::open(later_path);
-    if let Ok(later_file) = later_file {
-        let reader = BufReader::new(later_file);
-        reader.lines().for_each(|line| {
-            let line = line.expect("Failed to read line");
+    if later {
+        let later_path = get_or_make_file("later".to_string());
+        let content = std::fs::read_to_string(later_path).expect("Failed to read later file");
+        let visible = visible_lines(&DayFile::parse(&content), tags);
+        for (line, _) in content.lines().zip(visible).filter(|(_, v)| *v) {
             combined_file
                 .write_fmt(format_args!("{}\n", line))
                 .expect("failed to write line");
-        });
+        }
+    }
+
+    if !edit_combined(&doto_path, &combined_name) {
+        std::process::exit(1);
+    }
+}
+
+/// Opens a combined view in the editor and writes it back to the todo files,
+/// re-opening it until it's valid or the user gives up. Returns false if it
+/// wasn't saved.
+fn edit_combined(doto_path: &str, combined_name: &str) -> bool {
+    let combined_path = format!("{}/{}.md", doto_path, combined_name);
+    loop {
+        open_file(combined_name.to_string());
+
+        let combined =
+            std::fs::read_to_string(&combined_path).expect("Failed to read combined file");
+        match parse_combined(doto_path, &combined) {
+            Ok(sections) => {
+                write_sections(doto_path, &sections);
+                break;
+            }
+            Err(errors) => {
+                print_errors(&errors);
+                if !confirm("Re-open the editor to fix it?", true) {
+                    eprintln!(
+                        "No todo files were changed, your edits are in {}",
+                        combined_path
+                    );
+                    return false;
+                }
+            }
+        }
     }
 
-    open_file("todo".to_string());
+    // delete the combined file
+    std::fs::remove_file(combined_path).expect("Failed to remove combined file");
+    true
+}
 
-    let combined_file = File::open(combined_path.clone()).expect("Failed to open combined file");
-    let reader = BufReader::new(combined_file);
-    let mut current_file: Option<File> = None;
+/// Offers to apply or discard combined views that a previous session never
+/// saved, e.g. because the editor or terminal died. Only call this while
+/// holding the doto lock, otherwise it could pick up a view that is still open.
+pub fn recover_combined() {
+    let doto_path = get_doto_path();
+    let mut leftovers = std::fs::read_dir(&doto_path)
+        .expect("Could not read doto directory")
+        .filter_map(|f| f.ok())
+        .filter_map(|f| f.file_name().to_str().map(|name| name.to_string()))
+        .filter(|name| name == "todo.md" || (name.starts_with(".todo-") && name.ends_with(".md")))
+        .collect::<Vec<_>>();
+    leftovers.sort();
+
+    for file_name in leftovers {
+        let combined_name = file_name.trim_end_matches(".md");
+        let combined_path = format!("{}/{}", doto_path, file_name);
+        let combined =
+            std::fs::read_to_string(&combined_path).expect("Failed to read combined file");
+        let sections = parse_combined(&doto_path, &combined);
+
+        println!(
+            "Found a combined view that was never saved: {}",
+            combined_path
+        );
+        match &sections {
+            Ok(sections) => {
+                let diff = diff_sections(&doto_path, sections);
+                if diff.is_empty() {
+                    println!("It matches your todo files, removing it.");
+                    std::fs::remove_file(&combined_path).expect("Failed to remove combined file");
+                    continue;
+                }
+                print!("{}", diff);
+            }
+            Err(errors) => print_errors(errors),
+        }
 
-    for line in reader.lines() {
-        let line = line.expect("Failed to read line");
-        if line.eq("---") {
+        loop {
+            match ask("[a]pply, [d]iscard, [r]eopen in editor or [s]kip for now?").as_str() {
+                "a" | "apply" => {
+                    let Ok(sections) = &sections else {
+                        eprintln!("It can't be applied until it's fixed, reopen it instead");
+                        continue;
+                    };
+                    write_sections(&doto_path, sections);
+                    std::fs::remove_file(&combined_path).expect("Failed to remove combined file");
+                }
+                "d" | "discard" => {
+                    std::fs::remove_file(&combined_path).expect("Failed to remove combined file");
+                }
+                "r" | "reopen" => {
+                    edit_combined(&doto_path, combined_name);
+                }
+                "s" | "skip" | "" => {}
+                _ => continue,
+            }
+            break;
+        }
+    }
+}
+
+fn print_errors(errors: &[String]) {
+    eprintln!("Could not save the combined view:");
+    for error in errors {
+        eprintln!("  {}", error);
+    }
+}
+
+/// Unified diff of each section against the todo file it would be saved to.
+fn diff_sections(doto_path: &str, sections: &[(String, String)]) -> String {
+    let mut diff = String::new();
+    for (name, content) in sections {
+        let path = format!("{}/{}.md", doto_path, name);
+        let existing = std::fs::read_to_string(&path).unwrap_or_default();
+        let content = match_trailing_newline(&existing, content);
+        if existing != content {
+            let file_name = format!("{}.md", name);
+            diff.push_str(
+                &TextDiff::from_lines(existing.as_str(), content)
+                    .unified_diff()
+                    .header(&file_name, &file_name)
+                    .to_string(),
+            );
+        }
+    }
+    diff
+}
+
+/// The combined view ends every line with a newline, files made by doto don't.
+fn match_trailing_newline<'a>(existing: &str, content: &'a str) -> &'a str {
+    if existing.ends_with('\n') {
+        content
+    } else {
+        content.strip_suffix('\n').unwrap_or(content)
+    }
+}
+
+/// Splits the edited combined view into the todo files to write, putting back
+/// lines hidden by a tag filter and saving edits made under `# overdue` to the
+/// files those tasks came from.
+fn parse_combined(doto_path: &str, combined: &str) -> Result<Vec<(String, String)>, Vec<String>> {
+    let first_line = combined.lines().next().unwrap_or_default();
+    let tags = parse_filter_line(first_line).unwrap_or_default();
+    // keep the newline so line numbers in errors still match the file
+    let combined = if tags.is_empty() {
+        combined
+    } else {
+        &combined[first_line.len()..]
+    };
+
+    let mut sections = split_combined(combined)?;
+    if !tags.is_empty() {
+        for (name, content) in sections.iter_mut().filter(|(name, _)| name != OVERDUE) {
+            let path = format!("{}/{}.md", doto_path, name);
+            let existing = std::fs::read_to_string(&path).unwrap_or_default();
+            *content = merge_hidden(&existing, content, &tags);
+        }
+    }
+    let Some(i) = sections.iter().position(|(name, _)| name == OVERDUE) else {
+        return Ok(sections);
+    };
+    let (_, overdue) = sections.remove(i);
+    for (name, content) in apply_overdue(doto_path, &overdue, &sections)? {
+        match sections.iter_mut().find(|(n, _)| *n == name) {
+            Some(section) => section.1 = content,
+            None => sections.push((name, content)),
+        }
+    }
+    Ok(sections)
+}
+
+/// Splits the edited combined view into `(file name, content)` pairs for each
+/// `# ` heading, checking every heading before anything gets written.
+fn split_combined(combined: &str) -> Result<Vec<(String, String)>, Vec<String>> {
+    let mut sections: Vec<(String, String)> = vec![];
+    let mut errors = vec![];
+
+    for (i, line) in combined.lines().enumerate() {
+        let line = strip_age(line);
+        if line == "---" {
             continue; // dont' write the --- line
         }
         if line.starts_with("# ") {
-            if let Some(mut file) = current_file {
-                file.flush().expect("Failed to flush file");
-            }
-            let date_str = line.trim_start_matches("# ").trim();
-
-            // TODO: we could probs simplify this by just not parsing the date and just using # to find title
-            // I feel that this might be a bit brittle though as user may want to use # elsewhere in file?
-            // should probably just read line after `---`
-            let mut path = PathBuf::from(format!("{}/{}.md", doto_path, "later"));
-            if date_str != "later" {
-                let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d").expect("Invalid date");
-                path = PathBuf::from(format!("{}/{}.md", doto_path, date.format("%Y-%m-%d")));
+            let title = line.trim_start_matches("# ").trim();
+            let name = if title == "later" || title == OVERDUE {
+                Some(title.to_string())
+            } else {
+                NaiveDate::parse_from_str(title, "%Y-%m-%d")
+                    .ok()
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+            };
+            match name {
+                Some(name) if sections.iter().any(|(n, _)| *n == name) => {
+                    errors.push(format!(
+                        "line {}: \"{}\" appears more than once",
+                        i + 1,
+                        line
+                    ));
+                }
+                Some(name) => sections.push((name, String::new())),
+                None => errors.push(format!(
+                    "line {}: \"{}\" should be a date (YYYY-MM-DD) or \"later\"",
+                    i + 1,
+                    line
+                )),
             }
-            let file = File::create(path).expect("Failed to create file");
-            current_file = Some(file);
+        } else if sections.is_empty() && errors.is_empty() && !line.trim().is_empty() {
+            errors.push(format!(
+                "line {}: \"{}\" is not under a date heading",
+                i + 1,
+                line
+            ));
         }
-        if let Some(file) = &mut current_file {
-            writeln!(file, "{}", line).expect("Failed to write line");
+        if let Some((_, content)) = sections.last_mut() {
+            content.push_str(line);
+            content.push('\n');
         }
     }
 
-    if let Some(mut file) = current_file {
-        file.flush().expect("Failed to flush file");
+    if errors.is_empty() {
+        Ok(sections)
+    } else {
+        Err(errors)
     }
+}
 
-    // delete the combined file
-    std::fs::remove_file(combined_path).expect("Failed to remove combined file");
+/// Writes every changed section to a temporary file and only moves them over
+/// the todo files once all of them were written, so a failure changes nothing.
+/// Unchanged files are left alone to keep their mtimes meaningful for sync.
+fn write_sections(doto_path: &str, sections: &[(String, String)]) {
+    let mut written: Vec<(PathBuf, PathBuf)> = vec![];
+    for (name, content) in sections {
+        let path = PathBuf::from(format!("{}/{}.md", doto_path, name));
+        let mut content = content.as_str();
+        if let Ok(existing) = std::fs::read_to_string(&path) {
+            content = match_trailing_newline(&existing, content);
+            if existing == content {
+                continue;
+            }
+        }
+        let tmp_path = path.with_extension("md.tmp");
+        if let Err(err) = std::fs::write(&tmp_path, content) {
+            for (tmp_path, _) in &written {
+                let _ = std::fs::remove_file(tmp_path);
+            }
+            panic!("Failed to write {:?}: {}", tmp_path, err);
+        }
+        written.push((tmp_path, path));
+    }
+    for (tmp_path, path) in written {
+        std::fs::rename(&tmp_path, &path).expect("Failed to replace todo file");
+    }
 }
 
 pub fn get_all_files() -> Vec<PathBuf> {
@@ -118,149 +419,274 @@ pub fn get_all_files() -> Vec<PathBuf> {
         .filter(|f| f.is_file())
         .filter(|f| {
             let file_name = f.file_name().unwrap().to_str().unwrap();
-            file_name.ends_with(".md") && file_name != "later.md"
+            file_name.ends_with(".md")
+                && file_name != "later.md"
+                && !file_name.starts_with('.')
+                && !is_conflict_file(file_name)
         })
         .collect::<Vec<PathBuf>>();
     files.extend(todo_files);
     files
 }
 
-fn line_is_todo(l: &str) -> bool {
-    return l.trim().starts_with("- [ ]") || l.trim().starts_with("- []");
-}
-
-// TODO: create stats
-#[allow(dead_code)]
-fn line_is_completed_todo(l: &str) -> bool {
-    return l.trim().starts_with("- [x]") || l.trim().starts_with("- [X]");
-}
-
-#[allow(dead_code)]
-fn line_is_rescheduled_todo(l: &str) -> bool {
-    return l.trim().starts_with("- [>]");
-}
-
-#[allow(dead_code)]
-fn line_is_note(l: &str) -> bool {
-    return l.trim().starts_with("- ");
-}
-
-pub fn move_undone() {
+/// Moves every open task from past day files to today's, oldest day first.
+/// With `by_priority` they're added highest priority first instead, and with
+/// `dry_run` nothing is written, only what would move is printed.
+pub fn move_undone(by_priority: bool, dry_run: bool) {
     // move all undone tasks to today's todo file
+    let today = chrono::Local::now().date_naive();
 
     // get all todo files in the past
-    let todo_files = std::fs::read_dir(get_doto_path())
-        .expect("Could not read doto directory")
-        .filter(|f| f.is_ok())
-        .map(|f| f.expect("Unable to read file").path())
-        .filter(|f| f.is_file())
-        .filter(
-            |f| match f.extension().expect("Could not get file extension") {
-                ext if ext == "md" => true,
-                _ => false,
-            },
-        )
-        .filter(|f| {
-            let file_date = match f.file_name().and_then(|name| name.to_str()) {
-                Some(name) if name.ends_with(".md") => {
-                    match chrono::NaiveDate::parse_from_str(&name[..name.len() - 3], "%Y-%m-%d") {
-                        Ok(date) => Some(date),
-                        Err(_) => None,
-                    }
-                }
-                _ => None,
-            };
-
-            if let Some(file_date) = file_date {
-                return file_date < chrono::Local::now().naive_local().date();
-            }
-            return false; // ignore files that don't have a date in their name
-        })
+    let mut todo_files = get_all_files()
+        .into_iter()
+        .filter_map(|f| date_from_path(&f).map(|date| (f, date)))
+        .filter(|(_, date)| *date < today) // ignore files that don't have a date in their name
         .collect::<Vec<_>>();
+    todo_files.sort_by_key(|(_, date)| *date);
 
-    // loop through all todo files and find lines starting with "- [ ]"
-    let mut undone_task_count = 0;
-    for file in todo_files {
-        let file_content = std::fs::read_to_string(file.clone()).unwrap();
-        let lines = file_content.lines().collect::<Vec<_>>();
-        let undone_tasks = lines
-            .iter()
-            .filter(|l| line_is_todo(l)) // append undone tasks to today's todo file
-            .map(|l| {
-                let filename = file.file_name().unwrap().to_str().unwrap();
-                let truncated_file_name = &filename[..filename.len() - 3]; // Remove last three characters
-
-                format!("{} ({})", l, truncated_file_name)
-            })
-            .collect::<Vec<_>>();
-
-        let updated_file_content = lines
-            .iter()
-            .map(|l| {
-                if line_is_todo(l) {
-                    let whitespace = l
-                        .chars()
-                        .take_while(|c| c.is_whitespace())
-                        .collect::<String>();
-
-                    // add date to line
-                    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
-                    format!("{}- [>] ({}) {}", whitespace, date, &l[6..])
-                } else {
-                    l.to_string()
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("\n");
+    let today_filename = get_today_filename();
+    let today_todo_file = if dry_run {
+        PathBuf::from(format!("{}/{}.md", get_doto_path(), today_filename))
+    } else {
+        PathBuf::from(get_or_make_file(today_filename.clone()))
+    };
+    let mut today_todo = match DayFile::load(&today_todo_file) {
+        Ok(day_file) => day_file,
+        Err(_) if dry_run => DayFile::default(),
+        Err(err) => panic!("Could not read today's todo file: {}", err),
+    };
+    let mut existing = today_todo
+        .tasks()
+        .map(|t| chain_key(&t.text))
+        .collect::<HashSet<_>>();
+
+    // loop through all todo files and find open tasks
+    let mut blocks = vec![];
+    let mut changed_files = vec![];
+    let mut duplicate_count = 0;
+    for (file, file_date) in todo_files {
+        let mut day_file = DayFile::load(&file).expect("Could not read todo file");
+        let (file_blocks, duplicates) =
+            take_undone_blocks(&mut day_file, file_date, today, &mut existing);
+        duplicate_count += duplicates;
+
+        if file_blocks.is_empty() {
+            continue;
+        }
 
-        std::fs::write(file.clone(), updated_file_content).unwrap();
+        blocks.extend(file_blocks);
+        changed_files.push((file, day_file));
+    }
+    if by_priority {
+        // subtasks stay with their parent, whose priority decides
+        blocks.sort_by_key(|b| {
+            let priority = b.tasks[0].priority();
+            (priority.is_none(), priority)
+        });
+    }
 
-        undone_task_count += undone_tasks.len();
+    // add undone tasks to the same section of today's todo file
+    let mut undone_task_count = 0;
+    for block in blocks {
+        let tasks = block.tasks;
+        undone_task_count += tasks.len();
+        if dry_run {
+            let section = match &block.section {
+                Some(heading) => format!(" {}", heading),
+                None => String::new(),
+            };
+            for task in &tasks {
+                println!(
+                    "{} -> {}{}: {}{}",
+                    task.origin.unwrap().format("%Y-%m-%d"),
+                    today_filename,
+                    section,
+                    task.indent,
+                    task.text
+                );
+            }
+        } else if !tasks.is_empty() {
+            today_todo.insert_in_section(block.section.as_ref(), tasks);
+        }
+    }
 
-        if undone_tasks.len() == 0 {
-            continue;
+    if dry_run {
+        println!(
+            "Would move {} undone tasks to today's todo file",
+            undone_task_count
+        );
+        if duplicate_count > 0 {
+            println!(
+                "Would leave {} tasks that are already in today's todo file",
+                duplicate_count
+            );
         }
+        return;
+    }
 
-        // append undone tasks to today's todo file
-        let today_todo_file = get_today_todo_file_path();
-        let mut today_todo_content = std::fs::read_to_string(today_todo_file.clone()).unwrap();
-        today_todo_content.push_str("\n");
-        today_todo_content.push_str(&undone_tasks.join("\n"));
-        std::fs::write(today_todo_file, today_todo_content).unwrap();
+    // today's file is written first, so if anything fails the tasks are in
+    // both places rather than lost
+    today_todo
+        .save(&today_todo_file)
+        .expect("Could not write today's todo file");
+    for (file, day_file) in changed_files {
+        day_file.save(&file).expect("Could not write todo file");
     }
 
     println!(
         "Moved {} undone tasks to today's todo file",
         undone_task_count
     );
+    if duplicate_count > 0 {
+        println!(
+            "Left {} tasks that were already in today's todo file",
+            duplicate_count
+        );
+    }
+}
+
+/// An open task, its open subtasks and the `##` heading they were under.
+struct UndoneBlock {
+    section: Option<Heading>,
+    tasks: Vec<Task>,
 }
 
-fn get_or_make_file(filename: String) -> String {
+/// Marks every open task in `day_file` as moved to `today` and returns copies
+/// of them, with subtasks kept under their parent. A task whose text is in
+/// `existing` (today's file and what was moved so far) is left open where it
+/// is, subtasks and all. Also returns how many tasks were left that way.
+fn take_undone_blocks(
+    day_file: &mut DayFile,
+    file_date: NaiveDate,
+    today: NaiveDate,
+    existing: &mut HashSet<String>,
+) -> (Vec<UndoneBlock>, usize) {
+    let mut blocks: Vec<UndoneBlock> = vec![];
+    let mut section: Option<Heading> = None;
+    // (depth, index of the block the task was moved into) for each enclosing task
+    let mut parents: Vec<(usize, Option<usize>)> = vec![];
+    let mut root_indent = String::new();
+    // depth of the task being left in place, while going through its subtasks
+    let mut skipping: Option<usize> = None;
+    let mut skipped = 0;
+
+    for line in day_file.lines.iter_mut() {
+        let task = match line {
+            Line::Heading(heading) => {
+                section = (heading.level > 1).then(|| heading.clone());
+                parents.clear();
+                skipping = None;
+                continue;
+            }
+            Line::Task(task) => task,
+            Line::Text(_) => continue,
+        };
+
+        if skipping.is_some_and(|depth| task.depth() > depth) {
+            skipped += task.is_open() as usize;
+            continue;
+        }
+        skipping = None;
+        while matches!(parents.last(), Some((depth, _)) if *depth >= task.depth()) {
+            parents.pop();
+        }
+        if !task.is_open() {
+            parents.push((task.depth(), None));
+            continue;
+        }
+        let is_root = !matches!(parents.last(), Some((_, Some(_))));
+        if is_root && existing.contains(&chain_key(&task.text)) {
+            skipping = Some(task.depth());
+            skipped += 1;
+            continue;
+        }
+
+        let mut moved = task.clone();
+        moved.origin = Some(file_date);
+        let block = match parents.last() {
+            Some((_, Some(block))) => {
+                if let Some(indent) = moved.indent.strip_prefix(root_indent.as_str()) {
+                    moved.indent = indent.to_string();
+                }
+                *block
+            }
+            _ => {
+                existing.insert(chain_key(&task.text));
+                root_indent = moved.indent.clone();
+                moved.indent = String::new();
+                blocks.push(UndoneBlock {
+                    section: section.clone(),
+                    tasks: vec![],
+                });
+                blocks.len() - 1
+            }
+        };
+        blocks[block].tasks.push(moved);
+        parents.push((task.depth(), Some(block)));
+
+        task.reschedule(today);
+    }
+    (blocks, skipped)
+}
+
+pub fn get_or_make_file(filename: String) -> String {
     let doto_path = get_doto_path();
     let todo_file = format!("{}/{}.md", doto_path, filename);
 
     // create todo file if it doesn't exist
     if !std::path::Path::new(&todo_file).exists() {
-        std::fs::write(&todo_file, format!("# {}", filename))
-            .expect("Unable to write a new todo file");
+        let content = match NaiveDate::parse_from_str(&filename, "%Y-%m-%d") {
+            Ok(date) => day_template(&doto_path, date),
+            Err(_) => format!("# {}", filename),
+        };
+        std::fs::write(&todo_file, content).expect("Unable to write a new todo file");
+    }
+
+    if let Some(date) = date_from_path(Path::new(&todo_file)) {
+        add_recurring_tasks(date, Path::new(&todo_file));
     }
 
-    return todo_file;
+    todo_file
+}
+
+/// Content for a new day file from `templates/<weekday>.md` (e.g.
+/// `templates/monday.md`) or `templates/default.md` in the doto directory.
+/// `{date}`, `{weekday}` and `{week}` are replaced, and the `# date` heading is
+/// added if the template doesn't start with it.
+fn day_template(doto_path: &str, date: NaiveDate) -> String {
+    let heading = format!("# {}", date.format("%Y-%m-%d"));
+    let weekday = date.format("%A").to_string();
+    let template = [weekday.to_lowercase().as_str(), "default"]
+        .iter()
+        .find_map(|name| {
+            std::fs::read_to_string(format!("{}/templates/{}.md", doto_path, name)).ok()
+        });
+    let Some(template) = template else {
+        return heading;
+    };
+
+    let content = template
+        .replace("{date}", &date.format("%Y-%m-%d").to_string())
+        .replace("{weekday}", &weekday)
+        .replace("{week}", &date.iso_week().week().to_string());
+    if content.lines().next() == Some(heading.as_str()) {
+        content
+    } else {
+        format!("{}\n{}", heading, content)
+    }
 }
 
 fn open_file(filename: String) {
-    let todo_file = get_or_make_file(filename);
+    open_file_at(filename, None);
+}
 
-    // open today's todo file in user's default editor
-    let editor = std::env::var("EDITOR").unwrap_or("vim".to_string());
-    Command::new(editor)
-        .arg(&todo_file)
-        .status()
-        .expect("Could not open file");
+pub fn open_file_at(filename: String, line: Option<usize>) {
+    let todo_file = get_or_make_file(filename);
+    open_in_editor(&todo_file, line);
 }
 
 #[allow(unused_assignments)]
-fn parse_day_string(date: String) -> Option<NaiveDate> {
+pub fn parse_day_string(date: String) -> Option<NaiveDate> {
     let today = chrono::Local::now().date_naive();
     let mut parsed_date: Option<NaiveDate> = None;
     if date.chars().all(|c| c.is_alphabetic()) {
@@ -294,22 +720,17 @@ fn parse_day_string(date: String) -> Option<NaiveDate> {
 
         let mut target_date = today;
         let days_until_monday = (today.weekday().num_days_from_monday() + 7 - 1) % 7;
-        target_date = target_date - Duration::days(days_until_monday as i64);
+        target_date -= Duration::days(days_until_monday as i64);
         let days_until_target = (target_weekday.num_days_from_monday() + 7 - 1) % 7;
-        target_date = target_date + Duration::days(days_until_target as i64);
+        target_date += Duration::days(days_until_target as i64);
 
         parsed_date = Some(target_date);
     } else {
         // parse the date for one or two -
         let date = match date.split("-").collect::<Vec<_>>().len() {
             3 => date,
-            2 => format!("{}-{}", today.year().to_string(), date),
-            1 => format!(
-                "{}-{}-{}",
-                today.year().to_string(),
-                today.month().to_string(),
-                date
-            ),
+            2 => format!("{}-{}", today.year(), date),
+            1 => format!("{}-{}-{}", today.year(), today.month(), date),
             _ => {
                 eprintln!("Invalid number of '-'. Expects YYYY-MM-DD, MM-DD or DD");
                 return None;
@@ -326,5 +747,154 @@ fn parse_day_string(date: String) -> Option<NaiveDate> {
         };
     }
 
-    return parsed_date;
+    parsed_date
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn take(content: &str, existing: &mut HashSet<String>) -> (String, Vec<UndoneBlock>, usize) {
+        let mut day_file = DayFile::parse(content);
+        let (blocks, skipped) = take_undone_blocks(
+            &mut day_file,
+            date("2023-05-20"),
+            date("2023-05-24"),
+            existing,
+        );
+        (day_file.to_string(), blocks, skipped)
+    }
+
+    fn block_lines(block: &UndoneBlock) -> Vec<String> {
+        block.tasks.iter().map(|t| t.to_string()).collect()
+    }
+
+    fn names(sections: &[(String, String)]) -> Vec<&str> {
+        sections.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    #[test]
+    fn splits_the_combined_view_by_heading() {
+        let sections = split_combined(
+            "\n# 2023-05-24\n- [ ] a\n---\n# 2023-5-25\n---\n# later\n- [ ] someday\n",
+        )
+        .unwrap();
+        assert_eq!(names(&sections), ["2023-05-24", "2023-05-25", "later"]);
+        assert_eq!(sections[0].1, "# 2023-05-24\n- [ ] a\n");
+        assert_eq!(sections[1].1, "# 2023-5-25\n");
+        assert_eq!(sections[2].1, "# later\n- [ ] someday\n");
+    }
+
+    #[test]
+    fn rejects_bad_headings() {
+        let errors = split_combined("# 2023-05-24\n---\n# tomorrow\n- [ ] a\n").unwrap_err();
+        assert_eq!(
+            errors,
+            ["line 3: \"# tomorrow\" should be a date (YYYY-MM-DD) or \"later\""]
+        );
+
+        let errors = split_combined("# 2023-05-24\n---\n# 2023-05-24\n").unwrap_err();
+        assert_eq!(errors, ["line 3: \"# 2023-05-24\" appears more than once"]);
+
+        let errors = split_combined("- [ ] lost\n# 2023-05-24\n").unwrap_err();
+        assert_eq!(
+            errors,
+            ["line 1: \"- [ ] lost\" is not under a date heading"]
+        );
+    }
+
+    #[test]
+    fn reports_every_bad_heading() {
+        let errors =
+            split_combined("# 2023-05-24\n---\n# 2023-13-01\n---\n# 2023-05-24\n").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("line 3:"));
+        assert!(errors[1].starts_with("line 5:"));
+    }
+
+    #[test]
+    fn parses_a_view_without_a_filter() {
+        let sections = parse_combined("/nonexistent", "# 2023-05-24\n- [ ] a\n---\n").unwrap();
+        assert_eq!(names(&sections), ["2023-05-24"]);
+        assert!(parse_combined("/nonexistent", "# nope\n").is_err());
+    }
+
+    #[test]
+    fn keeps_subtasks_with_their_parent() {
+        let (file, blocks, skipped) = take(
+            "# 2023-05-20\n- [ ] feature\n  - [x] plan\n  - [ ] write tests\n    - [ ] edge cases\n- [x] done\n  - [ ] orphan\n",
+            &mut HashSet::new(),
+        );
+        assert_eq!(
+            file,
+            "# 2023-05-20\n- [>] (2023-05-24) feature\n  - [x] plan\n  - [>] (2023-05-24) write tests\n    - [>] (2023-05-24) edge cases\n- [x] done\n  - [>] (2023-05-24) orphan\n"
+        );
+        assert_eq!(skipped, 0);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(
+            block_lines(&blocks[0]),
+            [
+                "- [ ] feature (2023-05-20)",
+                "  - [ ] write tests (2023-05-20)",
+                "    - [ ] edge cases (2023-05-20)"
+            ]
+        );
+        // an open subtask of a finished task is moved on its own
+        assert_eq!(block_lines(&blocks[1]), ["- [ ] orphan (2023-05-20)"]);
+    }
+
+    #[test]
+    fn remembers_sections() {
+        let (_, blocks, _) = take(
+            "# 2023-05-20\n- [ ] loose\n## work\n- [ ] report\n# notes\n- [ ] after\n",
+            &mut HashSet::new(),
+        );
+        let sections = blocks
+            .iter()
+            .map(|b| b.section.as_ref().map(|h| h.title.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(sections, [None, Some("work".to_string()), None]);
+    }
+
+    #[test]
+    fn leaves_duplicates_open() {
+        let mut existing = HashSet::from([chain_key("call bank")]);
+        let (file, blocks, skipped) = take(
+            "# 2023-05-20\n- [ ] call bank\n  - [ ] find number\n- [ ] other\n",
+            &mut existing,
+        );
+        assert_eq!(
+            file,
+            "# 2023-05-20\n- [ ] call bank\n  - [ ] find number\n- [>] (2023-05-24) other\n"
+        );
+        assert_eq!(skipped, 2);
+        assert_eq!(blocks.len(), 1);
+        assert!(existing.contains(&chain_key("other")));
+    }
+
+    #[test]
+    fn moves_a_task_from_several_days_once() {
+        let mut existing = HashSet::new();
+        let (_, first, _) = take("- [ ] call bank\n", &mut existing);
+        let (file, second, skipped) = take("- [ ] call bank (2023-05-18)\n", &mut existing);
+        assert_eq!(first.len(), 1);
+        assert!(second.is_empty());
+        assert_eq!(skipped, 1);
+        assert_eq!(file, "- [ ] call bank (2023-05-18)\n");
+    }
+
+    #[test]
+    fn moves_subtasks_with_the_same_text() {
+        let (_, blocks, skipped) = take(
+            "- [ ] feature A\n  - [ ] write tests\n- [ ] feature B\n  - [ ] write tests\n",
+            &mut HashSet::new(),
+        );
+        assert_eq!(skipped, 0);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1].tasks.len(), 2);
+    }
 }
@@ -1,3 +1,5 @@
+use std::io::Write;
+
 pub fn get_doto_path() -> String {
     let doto_path = std::env::var("DOTO_PATH").unwrap_or(format!(
         "{}/.doto",
@@ -12,14 +14,38 @@ pub fn get_doto_path() -> String {
 }
 
 // NOTE: does not include ".md"
-pub fn get_today_todo_file_path() -> String {
-    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
-    let path = format!("{}/{}.md", get_doto_path(), date);
-    path
+pub fn get_today_filename() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
 }
 
-#[allow(dead_code)]
-pub fn get_today_filename() -> String {
-    let date = chrono::Local::now().format("%Y-%m-%d").to_string();
-    return format!("{}", date);
+/// Asks a yes/no question on stdin, returning `default` when enter is pressed.
+/// A closed stdin counts as no, so a prompt asked in a loop can't spin forever.
+pub fn confirm(prompt: &str, default: bool) -> bool {
+    let options = if default { "[Y/n]" } else { "[y/N]" };
+    match read_answer(&format!("{} {}", prompt, options)).as_deref() {
+        Some("") => default,
+        Some("y" | "yes") => true,
+        _ => false,
+    }
+}
+
+/// Prints a prompt and returns the lowercased answer, or an empty string if
+/// stdin is closed.
+pub fn ask(prompt: &str) -> String {
+    read_answer(prompt).unwrap_or_default()
+}
+
+/// Like [`ask`], but `None` if stdin is closed.
+fn read_answer(prompt: &str) -> Option<String> {
+    print!("{} ", prompt);
+    std::io::stdout().flush().expect("Failed to flush stdout");
+
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(0) | Err(_) => {
+            println!();
+            None
+        }
+        Ok(_) => Some(answer.trim().to_lowercase()),
+    }
 }